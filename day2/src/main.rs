@@ -2,30 +2,97 @@ use anyhow::{format_err, Error};
 use clap::Parser;
 use smallvec::SmallVec;
 use std::cmp::Ordering;
-use std::convert::TryFrom;
+use std::fmt;
 use std::fs;
 use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Parser)]
 struct Input {
     input: PathBuf,
+    /// mapping of the first column, e.g. `A=rock,B=paper,C=scissors`
+    #[arg(long)]
+    opponent: Option<ColumnMapping<RPS>>,
+    /// mapping of the second column as a move, e.g. `X=rock,Y=paper,Z=scissors`
+    #[arg(long)]
+    response: Option<ColumnMapping<RPS>>,
+    /// mapping of the second column as an outcome, e.g. `X=lose,Y=draw,Z=win`
+    #[arg(long)]
+    outcome: Option<ColumnMapping<WLD>>,
+    /// file with `opponent = ...`, `response = ...` and `outcome = ...` lines
+    #[arg(long)]
+    mapping_file: Option<PathBuf>,
+    /// score the guide under every possible mapping of the second column
+    #[arg(long)]
+    all_mappings: bool,
+    /// compare the guide against the optimal strategy for its opponent moves
+    #[arg(long)]
+    analyze: bool,
+    /// play the guide in a round-robin against other guide files or built-in
+    /// strategies (always-rock, always-paper, always-scissors, copy-last, beat-last)
+    #[arg(long, value_delimiter = ',')]
+    tournament: Vec<String>,
+    /// number of rounds per match in the tournament
+    #[arg(long, default_value_t = 1000)]
+    rounds: usize,
 }
 
 fn main() -> Result<(), Error> {
     let opts = Input::parse();
 
-    let total_score = simple_iterator(&opts.input)?;
+    let mut mapping = match &opts.mapping_file {
+        Some(p) => fs::read_to_string(p)?.parse()?,
+        None => StrategyMapping::default(),
+    };
+    if let Some(opponent) = opts.opponent {
+        mapping.opponent = opponent;
+    }
+    if let Some(response) = opts.response {
+        mapping.response = response;
+    }
+    if let Some(outcome) = opts.outcome {
+        mapping.outcome = outcome;
+    }
+
+    if opts.all_mappings {
+        let buf = fs::read_to_string(&opts.input)?;
+        for (response, total_score) in score_all_responses(&buf, &mapping) {
+            println!("response {response}: total score {total_score}");
+        }
+        for (outcome, total_score) in score_all_outcomes(&buf, &mapping) {
+            println!("outcome {outcome}: total score {total_score}");
+        }
+        return Ok(());
+    }
+
+    if opts.analyze {
+        let buf = fs::read_to_string(&opts.input)?;
+        println!("{}", analyze_guide(&buf, &mapping));
+        return Ok(());
+    }
+
+    if !opts.tournament.is_empty() {
+        let mut strategies = vec![Strategy::from_guide(&opts.input, &mapping)?];
+        for name in &opts.tournament {
+            strategies.push(Strategy::from_name(name, &mapping)?);
+        }
+        println!("{}", LeagueTable(run_tournament(&strategies, opts.rounds)));
+        return Ok(());
+    }
+
+    let total_score = simple_iterator(&opts.input, &mapping)?;
     println!("total score: {total_score}");
-    let total_score = use_bufreader(&opts.input)?;
+    let total_score = use_bufreader(&opts.input, &mapping)?;
     println!("total score: {total_score}");
-    let total_score = simple_iterator2(&opts.input)?;
+    let total_score = simple_iterator2(&opts.input, &mapping)?;
     println!("total score: {total_score}");
-    let total_score = use_bufreader2(&opts.input)?;
+    let total_score = use_bufreader2(&opts.input, &mapping)?;
     println!("total score: {total_score}");
     Ok(())
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Clone, Copy, Debug)]
 enum RPS {
     Rock,
@@ -33,19 +100,35 @@ enum RPS {
     Scissors,
 }
 
-impl TryFrom<char> for RPS {
-    type Error = Error;
-    fn try_from(value: char) -> Result<Self, Self::Error> {
-        match value {
-            'A' | 'X' => Ok(Self::Rock),
-            'B' | 'Y' => Ok(Self::Paper),
-            'C' | 'Z' => Ok(Self::Scissors),
-            _ => Err(format_err!("Bad char")),
+impl FromStr for RPS {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "r" | "rock" => Ok(Self::Rock),
+            "p" | "paper" => Ok(Self::Paper),
+            "s" | "scissors" => Ok(Self::Scissors),
+            _ => Err(format_err!("Bad move {s}")),
+        }
+    }
+}
+
+impl fmt::Display for RPS {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rock => write!(f, "rock"),
+            Self::Paper => write!(f, "paper"),
+            Self::Scissors => write!(f, "scissors"),
         }
     }
 }
 
 impl RPS {
+    const ALL: [Self; 3] = [Self::Rock, Self::Paper, Self::Scissors];
+
+    fn index(self) -> usize {
+        self.to_u8() as usize - 1
+    }
+
     fn to_u8(self) -> u8 {
         match self {
             Self::Rock => 1,
@@ -90,23 +173,183 @@ fn calculate_score(play0: RPS, play1: RPS) -> u64 {
     }
 }
 
-fn get_score(s: &str) -> Option<u64> {
-    let chars: SmallVec<[char; 3]> = s.chars().collect();
-    let play0: RPS = (*chars.first()?).try_into().ok()?;
-    let play1: RPS = (*chars.get(2)?).try_into().ok()?;
-    let score = calculate_score(play0, play1);
-    Some(score)
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct ColumnMapping<T>([(char, T); 3]);
+
+impl<T: Copy + PartialEq> ColumnMapping<T> {
+    fn get(&self, c: char) -> Option<T> {
+        self.0.iter().find(|(k, _)| *k == c).map(|(_, v)| *v)
+    }
+
+    fn symbols(&self) -> [char; 3] {
+        self.0.map(|(k, _)| k)
+    }
+
+    fn values(&self) -> [T; 3] {
+        self.0.map(|(_, v)| v)
+    }
+
+    fn with_values(&self, values: [T; 3]) -> Self {
+        let symbols = self.symbols();
+        Self([0, 1, 2].map(|i| (symbols[i], values[i])))
+    }
+}
+
+impl<T> FromStr for ColumnMapping<T>
+where
+    T: FromStr<Err = Error> + Copy + PartialEq,
+{
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let entries: SmallVec<[(char, T); 3]> = s
+            .split(',')
+            .map(|entry| {
+                let (k, v) = entry
+                    .split_once('=')
+                    .ok_or_else(|| format_err!("Expected KEY=VALUE, got {entry}"))?;
+                let mut chars = k.trim().chars();
+                let k = match (chars.next(), chars.next()) {
+                    (Some(k), None) => k,
+                    _ => return Err(format_err!("Bad symbol {k}")),
+                };
+                Ok((k, v.trim().parse()?))
+            })
+            .collect::<Result<_, Error>>()?;
+        if entries.len() != 3 {
+            return Err(format_err!("Expected 3 entries, got {}", entries.len()));
+        }
+        for (i, (k, v)) in entries.iter().enumerate() {
+            for (k1, v1) in &entries[i + 1..] {
+                if k == k1 || v == v1 {
+                    return Err(format_err!("Mapping {s} is not one-to-one"));
+                }
+            }
+        }
+        Ok(Self([entries[0], entries[1], entries[2]]))
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for ColumnMapping<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (k, v)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{k}={v}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct StrategyMapping {
+    opponent: ColumnMapping<RPS>,
+    response: ColumnMapping<RPS>,
+    outcome: ColumnMapping<WLD>,
+}
+
+impl Default for StrategyMapping {
+    fn default() -> Self {
+        Self {
+            opponent: ColumnMapping([('A', RPS::Rock), ('B', RPS::Paper), ('C', RPS::Scissors)]),
+            response: ColumnMapping([('X', RPS::Rock), ('Y', RPS::Paper), ('Z', RPS::Scissors)]),
+            outcome: ColumnMapping([('X', WLD::Lose), ('Y', WLD::Draw), ('Z', WLD::Win)]),
+        }
+    }
+}
+
+impl FromStr for StrategyMapping {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mapping = Self::default();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, spec) = line
+                .split_once('=')
+                .ok_or_else(|| format_err!("Bad mapping line {line}"))?;
+            match key.trim() {
+                "opponent" => mapping.opponent = spec.parse()?,
+                "response" => mapping.response = spec.parse()?,
+                "outcome" => mapping.outcome = spec.parse()?,
+                key => return Err(format_err!("Unknown column {key}")),
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+impl StrategyMapping {
+    fn get_score(&self, s: &str) -> Option<u64> {
+        let chars: SmallVec<[char; 3]> = s.chars().collect();
+        let play0 = self.opponent.get(*chars.first()?)?;
+        let play1 = self.response.get(*chars.get(2)?)?;
+        let score = calculate_score(play0, play1);
+        Some(score)
+    }
+
+    fn get_score2(&self, s: &str) -> Option<u64> {
+        let chars: SmallVec<[char; 3]> = s.chars().collect();
+        let play0 = self.opponent.get(*chars.first()?)?;
+        let cond = self.outcome.get(*chars.get(2)?)?;
+        let play1 = choose_play(play0, cond);
+        let score = calculate_score(play0, play1);
+        Some(score)
+    }
+}
+
+fn permutations<T: Copy>([a, b, c]: [T; 3]) -> [[T; 3]; 6] {
+    [
+        [a, b, c],
+        [a, c, b],
+        [b, a, c],
+        [b, c, a],
+        [c, a, b],
+        [c, b, a],
+    ]
+}
+
+fn score_all_responses(buf: &str, mapping: &StrategyMapping) -> Vec<(ColumnMapping<RPS>, u64)> {
+    permutations(mapping.response.values())
+        .into_iter()
+        .map(|values| {
+            let response = mapping.response.with_values(values);
+            let mapping = StrategyMapping {
+                response,
+                ..*mapping
+            };
+            let total_score = buf.split('\n').filter_map(|s| mapping.get_score(s)).sum();
+            (response, total_score)
+        })
+        .collect()
 }
 
-fn simple_iterator(p: &Path) -> Result<u64, Error> {
+fn score_all_outcomes(buf: &str, mapping: &StrategyMapping) -> Vec<(ColumnMapping<WLD>, u64)> {
+    permutations(mapping.outcome.values())
+        .into_iter()
+        .map(|values| {
+            let outcome = mapping.outcome.with_values(values);
+            let mapping = StrategyMapping {
+                outcome,
+                ..*mapping
+            };
+            let total_score = buf.split('\n').filter_map(|s| mapping.get_score2(s)).sum();
+            (outcome, total_score)
+        })
+        .collect()
+}
+
+fn simple_iterator(p: &Path, mapping: &StrategyMapping) -> Result<u64, Error> {
     let buf = fs::read_to_string(p)?;
-    let total_score = buf.split('\n').filter_map(get_score).sum();
+    let total_score = buf.split('\n').filter_map(|s| mapping.get_score(s)).sum();
     Ok(total_score)
 }
 
-fn use_bufreader(p: &Path) -> Result<u64, Error> {
+fn use_bufreader(p: &Path, mapping: &StrategyMapping) -> Result<u64, Error> {
     let f = fs::File::open(p)?;
-    let mut it = BufReadIter::new(f);
+    let mut it = BufReadIter::with_mapping(f, *mapping);
     let total_score = it.try_fold(0, |total_score, result| {
         result.map(|score| total_score + score)
     })?;
@@ -116,13 +359,20 @@ fn use_bufreader(p: &Path) -> Result<u64, Error> {
 struct BufReadIter<T: Read> {
     reader: BufReader<T>,
     line: String,
+    mapping: StrategyMapping,
 }
 
 impl<T: Read> BufReadIter<T> {
+    #[cfg(test)]
     fn new(read: T) -> Self {
+        Self::with_mapping(read, StrategyMapping::default())
+    }
+
+    fn with_mapping(read: T, mapping: StrategyMapping) -> Self {
         Self {
             reader: BufReader::new(read),
             line: String::new(),
+            mapping,
         }
     }
 }
@@ -133,7 +383,7 @@ impl<T: Read> Iterator for BufReadIter<T> {
         match self.reader.read_line(&mut self.line) {
             Ok(0) => None,
             Ok(_) => {
-                let score = get_score(&self.line).unwrap_or(0);
+                let score = self.mapping.get_score(&self.line).unwrap_or(0);
                 self.line.clear();
                 Some(Ok(score))
             }
@@ -142,6 +392,7 @@ impl<T: Read> Iterator for BufReadIter<T> {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Clone, Copy, Debug)]
 enum WLD {
     Lose,
@@ -149,14 +400,24 @@ enum WLD {
     Draw,
 }
 
-impl TryFrom<char> for WLD {
-    type Error = Error;
-    fn try_from(value: char) -> Result<Self, Self::Error> {
-        match value {
-            'X' => Ok(Self::Lose),
-            'Y' => Ok(Self::Draw),
-            'Z' => Ok(Self::Win),
-            _ => Err(format_err!("Bad char")),
+impl FromStr for WLD {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "l" | "lose" => Ok(Self::Lose),
+            "w" | "win" => Ok(Self::Win),
+            "d" | "draw" => Ok(Self::Draw),
+            _ => Err(format_err!("Bad outcome {s}")),
+        }
+    }
+}
+
+impl fmt::Display for WLD {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lose => write!(f, "lose"),
+            Self::Win => write!(f, "win"),
+            Self::Draw => write!(f, "draw"),
         }
     }
 }
@@ -177,24 +438,15 @@ fn choose_play(play0: RPS, result: WLD) -> RPS {
     }
 }
 
-fn get_score2(s: &str) -> Option<u64> {
-    let chars: SmallVec<[char; 3]> = s.chars().collect();
-    let play0: RPS = (*chars.first()?).try_into().ok()?;
-    let cond: WLD = (*chars.get(2)?).try_into().ok()?;
-    let play1 = choose_play(play0, cond);
-    let score = calculate_score(play0, play1);
-    Some(score)
-}
-
-fn simple_iterator2(p: &Path) -> Result<u64, Error> {
+fn simple_iterator2(p: &Path, mapping: &StrategyMapping) -> Result<u64, Error> {
     let buf = fs::read_to_string(p)?;
-    let total_score = buf.split('\n').filter_map(get_score2).sum();
+    let total_score = buf.split('\n').filter_map(|s| mapping.get_score2(s)).sum();
     Ok(total_score)
 }
 
-fn use_bufreader2(p: &Path) -> Result<u64, Error> {
+fn use_bufreader2(p: &Path, mapping: &StrategyMapping) -> Result<u64, Error> {
     let f = fs::File::open(p)?;
-    let mut it = BufReadIter2::new(f);
+    let mut it = BufReadIter2::with_mapping(f, *mapping);
     let total_score = it.try_fold(0, |total_score, result| {
         result.map(|score| total_score + score)
     })?;
@@ -204,13 +456,20 @@ fn use_bufreader2(p: &Path) -> Result<u64, Error> {
 struct BufReadIter2<T: Read> {
     reader: BufReader<T>,
     line: String,
+    mapping: StrategyMapping,
 }
 
 impl<T: Read> BufReadIter2<T> {
+    #[cfg(test)]
     fn new(read: T) -> Self {
+        Self::with_mapping(read, StrategyMapping::default())
+    }
+
+    fn with_mapping(read: T, mapping: StrategyMapping) -> Self {
         Self {
             reader: BufReader::new(read),
             line: String::new(),
+            mapping,
         }
     }
 }
@@ -221,7 +480,7 @@ impl<T: Read> Iterator for BufReadIter2<T> {
         match self.reader.read_line(&mut self.line) {
             Ok(0) => None,
             Ok(_) => {
-                let score = get_score2(&self.line).unwrap_or(0);
+                let score = self.mapping.get_score2(&self.line).unwrap_or(0);
                 self.line.clear();
                 Some(Ok(score))
             }
@@ -230,6 +489,276 @@ impl<T: Read> Iterator for BufReadIter2<T> {
     }
 }
 
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct GuideAnalysis {
+    rounds: u64,
+    opponent_counts: [u64; 3],
+    best_responses: [RPS; 3],
+    best_response_score: u64,
+    mixed_strategy: [f64; 3],
+    mixed_strategy_score: f64,
+    guide_score: u64,
+    guide_score2: u64,
+}
+
+impl GuideAnalysis {
+    fn expected_score(total: f64, rounds: u64) -> f64 {
+        if rounds == 0 {
+            0.0
+        } else {
+            total / rounds as f64
+        }
+    }
+}
+
+impl fmt::Display for GuideAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rounds: {}", self.rounds)?;
+        for play0 in RPS::ALL {
+            let count = self.opponent_counts[play0.index()];
+            let frequency = Self::expected_score(count as f64, self.rounds);
+            let best = self.best_responses[play0.index()];
+            writeln!(
+                f,
+                "opponent {play0}: {count} ({frequency:.3}), best response {best} scores {}",
+                calculate_score(play0, best)
+            )?;
+        }
+        let best = self.best_response_score as f64;
+        writeln!(
+            f,
+            "best fixed responses: total {best}, expected {:.3}",
+            Self::expected_score(best, self.rounds)
+        )?;
+        let mixed: SmallVec<[String; 3]> = RPS::ALL
+            .iter()
+            .map(|p| format!("{p}={:.3}", self.mixed_strategy[p.index()]))
+            .collect();
+        writeln!(
+            f,
+            "best mixed strategy {}: total {:.1}, expected {:.3}",
+            mixed.join(","),
+            self.mixed_strategy_score,
+            Self::expected_score(self.mixed_strategy_score, self.rounds)
+        )?;
+        for (label, score) in [("moves", self.guide_score), ("outcomes", self.guide_score2)] {
+            writeln!(
+                f,
+                "guide as {label}: total {score}, expected {:.3}, {} below optimal",
+                Self::expected_score(score as f64, self.rounds),
+                self.best_response_score - score
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn best_response(play0: RPS) -> RPS {
+    RPS::ALL
+        .into_iter()
+        .max_by_key(|play1| calculate_score(play0, *play1))
+        .unwrap_or(play0)
+}
+
+fn analyze_guide(buf: &str, mapping: &StrategyMapping) -> GuideAnalysis {
+    let mut opponent_counts = [0; 3];
+    let mut guide_score = 0;
+    let mut guide_score2 = 0;
+    for line in buf.split('\n') {
+        let Some(play0) = line.chars().next().and_then(|c| mapping.opponent.get(c)) else {
+            continue;
+        };
+        opponent_counts[play0.index()] += 1;
+        guide_score += mapping.get_score(line).unwrap_or(0);
+        guide_score2 += mapping.get_score2(line).unwrap_or(0);
+    }
+    let rounds = opponent_counts.iter().sum();
+
+    let best_responses = RPS::ALL.map(best_response);
+    let best_response_score = RPS::ALL
+        .iter()
+        .map(|p| opponent_counts[p.index()] * calculate_score(*p, best_responses[p.index()]))
+        .sum();
+
+    let pure_scores = RPS::ALL.map(|play1| {
+        RPS::ALL
+            .iter()
+            .map(|p| opponent_counts[p.index()] * calculate_score(*p, play1))
+            .sum::<u64>()
+    });
+    let max_score = pure_scores.iter().copied().max().unwrap_or(0);
+    let nbest = pure_scores.iter().filter(|s| **s == max_score).count();
+    let mixed_strategy = pure_scores.map(|s| {
+        if s == max_score {
+            1.0 / nbest as f64
+        } else {
+            0.0
+        }
+    });
+
+    GuideAnalysis {
+        rounds,
+        opponent_counts,
+        best_responses,
+        best_response_score,
+        mixed_strategy,
+        mixed_strategy_score: max_score as f64,
+        guide_score,
+        guide_score2,
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+enum Strategy {
+    Always(RPS),
+    CopyLast,
+    BeatLast,
+    Guide { name: String, moves: Vec<RPS> },
+}
+
+impl Strategy {
+    fn from_name(name: &str, mapping: &StrategyMapping) -> Result<Self, Error> {
+        match name {
+            "always-rock" => Ok(Self::Always(RPS::Rock)),
+            "always-paper" => Ok(Self::Always(RPS::Paper)),
+            "always-scissors" => Ok(Self::Always(RPS::Scissors)),
+            "copy-last" => Ok(Self::CopyLast),
+            "beat-last" => Ok(Self::BeatLast),
+            _ => Self::from_guide(Path::new(name), mapping),
+        }
+    }
+
+    fn from_guide(p: &Path, mapping: &StrategyMapping) -> Result<Self, Error> {
+        let buf = fs::read_to_string(p)?;
+        let moves = buf
+            .split('\n')
+            .filter_map(|s| s.chars().nth(2))
+            .filter_map(|c| mapping.response.get(c))
+            .collect();
+        Ok(Self::Guide {
+            name: p.to_string_lossy().into_owned(),
+            moves,
+        })
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Self::Always(play) => format!("always-{play}"),
+            Self::CopyLast => "copy-last".into(),
+            Self::BeatLast => "beat-last".into(),
+            Self::Guide { name, .. } => name.clone(),
+        }
+    }
+
+    fn play(&self, round: usize, last_opponent: Option<RPS>) -> RPS {
+        match self {
+            Self::Always(play) => *play,
+            Self::CopyLast => last_opponent.unwrap_or(RPS::Rock),
+            Self::BeatLast => last_opponent.map_or(RPS::Rock, best_response),
+            Self::Guide { moves, .. } => {
+                if moves.is_empty() {
+                    RPS::Rock
+                } else {
+                    moves[round % moves.len()]
+                }
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Default)]
+struct LeagueEntry {
+    name: String,
+    wins: u64,
+    draws: u64,
+    losses: u64,
+    total_score: u64,
+}
+
+impl LeagueEntry {
+    fn points(&self) -> u64 {
+        3 * self.wins + self.draws
+    }
+}
+
+fn play_match(strategy0: &Strategy, strategy1: &Strategy, rounds: usize) -> (u64, u64) {
+    let mut last0 = None;
+    let mut last1 = None;
+    let mut scores = (0, 0);
+    for round in 0..rounds {
+        let play0 = strategy0.play(round, last1);
+        let play1 = strategy1.play(round, last0);
+        scores.0 += calculate_score(play1, play0);
+        scores.1 += calculate_score(play0, play1);
+        last0 = Some(play0);
+        last1 = Some(play1);
+    }
+    scores
+}
+
+fn run_tournament(strategies: &[Strategy], rounds: usize) -> Vec<LeagueEntry> {
+    let mut table: Vec<LeagueEntry> = strategies
+        .iter()
+        .map(|s| LeagueEntry {
+            name: s.name(),
+            ..LeagueEntry::default()
+        })
+        .collect();
+    for i in 0..strategies.len() {
+        for j in i + 1..strategies.len() {
+            let (score0, score1) = play_match(&strategies[i], &strategies[j], rounds);
+            table[i].total_score += score0;
+            table[j].total_score += score1;
+            match score0.cmp(&score1) {
+                Ordering::Greater => {
+                    table[i].wins += 1;
+                    table[j].losses += 1;
+                }
+                Ordering::Less => {
+                    table[i].losses += 1;
+                    table[j].wins += 1;
+                }
+                Ordering::Equal => {
+                    table[i].draws += 1;
+                    table[j].draws += 1;
+                }
+            }
+        }
+    }
+    table.sort_by(|a, b| {
+        b.points()
+            .cmp(&a.points())
+            .then(b.total_score.cmp(&a.total_score))
+    });
+    table
+}
+
+struct LeagueTable(Vec<LeagueEntry>);
+
+impl fmt::Display for LeagueTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.0.iter().map(|e| e.name.len()).max().unwrap_or(0);
+        writeln!(
+            f,
+            "{:width$} {:>4} {:>4} {:>4} {:>6} {:>10}",
+            "", "W", "D", "L", "Pts", "Score"
+        )?;
+        for e in &self.0 {
+            writeln!(
+                f,
+                "{:width$} {:>4} {:>4} {:>4} {:>6} {:>10}",
+                e.name,
+                e.wins,
+                e.draws,
+                e.losses,
+                e.points(),
+                e.total_score
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
@@ -263,7 +792,8 @@ mod tests {
     #[test]
     fn test_score0() -> Result<(), Error> {
         let buf = include_str!("../input.txt");
-        let total_score: u64 = buf.split('\n').filter_map(get_score).sum();
+        let mapping = StrategyMapping::default();
+        let total_score: u64 = buf.split('\n').filter_map(|s| mapping.get_score(s)).sum();
         assert_eq!(total_score, 13565);
         Ok(())
     }
@@ -284,7 +814,8 @@ mod tests {
     #[test]
     fn test_score2() -> Result<(), Error> {
         let buf = include_str!("../input.txt");
-        let total_score: u64 = buf.split('\n').filter_map(get_score2).sum();
+        let mapping = StrategyMapping::default();
+        let total_score: u64 = buf.split('\n').filter_map(|s| mapping.get_score2(s)).sum();
         assert_eq!(total_score, 12424);
         Ok(())
     }
@@ -301,4 +832,98 @@ mod tests {
         assert_eq!(total_score, 12424);
         Ok(())
     }
+
+    #[test]
+    fn test_mapping() -> Result<(), Error> {
+        let mapping: StrategyMapping = "opponent = A=rock,B=paper,C=scissors\n\
+                                        response = X=r,Y=p,Z=s\n\
+                                        outcome = X=lose,Y=draw,Z=win"
+            .parse()?;
+        assert_eq!(mapping, StrategyMapping::default());
+
+        let response: ColumnMapping<RPS> = "X=paper,Y=rock,Z=scissors".parse()?;
+        assert_eq!(response.get('X'), Some(RPS::Paper));
+        assert_eq!(response.to_string(), "X=paper,Y=rock,Z=scissors");
+        let mapping = StrategyMapping {
+            response,
+            ..StrategyMapping::default()
+        };
+        assert_eq!(mapping.get_score("A X"), Some(8));
+
+        assert!("X=rock,Y=rock,Z=paper"
+            .parse::<ColumnMapping<RPS>>()
+            .is_err());
+        assert!("X=rock,Y=paper".parse::<ColumnMapping<RPS>>().is_err());
+        assert!("X=lose,Y=draw,Z=tie".parse::<ColumnMapping<WLD>>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_mappings() -> Result<(), Error> {
+        let buf = include_str!("../input.txt");
+        let mapping = StrategyMapping::default();
+        let responses = score_all_responses(buf, &mapping);
+        assert_eq!(responses.len(), 6);
+        assert_eq!(responses[0], (mapping.response, 13565));
+        let outcomes = score_all_outcomes(buf, &mapping);
+        assert_eq!(outcomes.len(), 6);
+        assert_eq!(outcomes[0], (mapping.outcome, 12424));
+        Ok(())
+    }
+
+    #[test]
+    fn test_analyze_guide() {
+        let analysis = analyze_guide("A Y\nB X\nC Z\n", &StrategyMapping::default());
+        assert_eq!(analysis.rounds, 3);
+        assert_eq!(analysis.opponent_counts, [1, 1, 1]);
+        assert_eq!(
+            analysis.best_responses,
+            [RPS::Paper, RPS::Scissors, RPS::Rock]
+        );
+        assert_eq!(analysis.best_response_score, 8 + 9 + 7);
+        assert_eq!(analysis.mixed_strategy, [0.0, 0.0, 1.0]);
+        assert_eq!(analysis.mixed_strategy_score, 18.0);
+        assert_eq!(analysis.guide_score, 15);
+        assert_eq!(analysis.guide_score2, 12);
+
+        let analysis = analyze_guide("A X\nA Y\nB Z\n", &StrategyMapping::default());
+        assert_eq!(analysis.mixed_strategy, [0.0, 1.0, 0.0]);
+        assert_eq!(analysis.mixed_strategy_score, 21.0);
+
+        let buf = include_str!("../input.txt");
+        let analysis = analyze_guide(buf, &StrategyMapping::default());
+        assert_eq!(analysis.guide_score, 13565);
+        assert_eq!(analysis.guide_score2, 12424);
+    }
+
+    #[test]
+    fn test_tournament() {
+        let guide = Strategy::Guide {
+            name: "guide".into(),
+            moves: vec![RPS::Paper, RPS::Scissors],
+        };
+        assert_eq!(guide.play(3, None), RPS::Scissors);
+        assert_eq!(Strategy::BeatLast.play(1, Some(RPS::Rock)), RPS::Paper);
+        assert_eq!(
+            play_match(&Strategy::Always(RPS::Rock), &Strategy::BeatLast, 3),
+            (4 + 1 + 1, 4 + 8 + 8)
+        );
+
+        let strategies = [
+            Strategy::Always(RPS::Rock),
+            Strategy::CopyLast,
+            Strategy::BeatLast,
+            guide,
+        ];
+        let table = run_tournament(&strategies, 10);
+        assert_eq!(table.len(), 4);
+        assert_eq!(
+            table
+                .iter()
+                .map(|e| e.wins + e.draws + e.losses)
+                .sum::<u64>(),
+            12
+        );
+        assert!(table.windows(2).all(|w| w[0].points() >= w[1].points()));
+    }
 }
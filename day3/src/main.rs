@@ -1,8 +1,8 @@
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use clap::Parser;
 use itertools::Itertools;
 use smallvec::SmallVec;
-use std::collections::HashSet;
+use std::fmt;
 use std::fs;
 use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
@@ -10,19 +10,68 @@ use std::path::{Path, PathBuf};
 #[derive(Parser)]
 struct Input {
     input: PathBuf,
+    /// number of elves sharing a badge
+    #[arg(long, default_value_t = 3)]
+    group_size: usize,
+    /// list malformed rucksacks and groups instead of only summing priorities
+    #[arg(long)]
+    diagnostics: bool,
+    /// plan item moves so that no item type is in both compartments
+    #[arg(long)]
+    plan: bool,
+    /// also move items between elves so every group shares exactly one badge
+    #[arg(long)]
+    between_elves: bool,
 }
 
 fn main() -> Result<(), Error> {
     let opts = Input::parse();
+    if opts.group_size == 0 {
+        return Err(format_err!("group size must be at least 1"));
+    }
+
+    if opts.diagnostics {
+        let buf = fs::read_to_string(&opts.input)?;
+        let report = diagnose(&buf, opts.group_size);
+        for diagnostic in &report.diagnostics {
+            println!("{diagnostic}");
+        }
+        println!("total priority {}", report.total_priority);
+        println!("total badge priority {}", report.total_badge_priority);
+        return Ok(());
+    }
+
+    if opts.plan {
+        let buf = fs::read_to_string(&opts.input)?;
+        let group_size = opts.between_elves.then_some(opts.group_size);
+        let plan = plan_reorganisation(&buf, group_size);
+        for item_move in &plan.moves {
+            println!("{item_move}");
+        }
+        for (i, rucksack) in plan.rucksacks.iter().enumerate() {
+            println!("elf {}: {rucksack}", i + 1);
+        }
+        for elf in &plan.unseparated_rucksacks {
+            println!("elf {} cannot separate its compartments", elf + 1);
+        }
+        for group in &plan.unresolved_groups {
+            println!(
+                "elves {} to {} cannot share exactly one badge",
+                group.start + 1,
+                group.end
+            );
+        }
+        return Ok(());
+    }
 
     let total_priority = simple_iterator(&opts.input)?;
     println!("total priority {total_priority}");
     let total_priority = use_bufreader(&opts.input)?;
     println!("total priority {total_priority}");
 
-    let total_priority = simple_iterator2(&opts.input)?;
+    let total_priority = simple_iterator2(&opts.input, opts.group_size)?;
     println!("total priority {total_priority}");
-    let total_priority = use_bufreader2(&opts.input)?;
+    let total_priority = use_bufreader2(&opts.input, opts.group_size)?;
     println!("total priority {total_priority}");
     Ok(())
 }
@@ -33,9 +82,9 @@ fn simple_iterator(p: &Path) -> Result<u64, Error> {
     let total_priority = buf
         .split('\n')
         .map(|s| {
-            let (left, right) = split_elements(s);
-            let common = common_element(left, right);
-            common.and_then(get_priority).unwrap_or(0)
+            rucksack_common_element(s)
+                .and_then(get_priority)
+                .unwrap_or(0)
         })
         .sum();
     Ok(total_priority)
@@ -70,8 +119,7 @@ impl<T: Read> Iterator for BufReadIter<T> {
         match self.reader.read_line(&mut self.line) {
             Ok(0) => None,
             Ok(_) => {
-                let (left, right) = split_elements(self.line.trim());
-                let common = common_element(left, right);
+                let common = rucksack_common_element(self.line.trim());
                 self.line.clear();
                 Some(Ok(common))
             }
@@ -88,52 +136,108 @@ fn get_priority(c: char) -> Option<u64> {
     }
 }
 
-fn split_elements(s: &str) -> (&str, &str) {
-    assert!(s.len() % 2 == 0, "Cannot handle this");
-    let split = s.len() / 2;
-    (&s[..split], &s[split..])
+fn item_from_priority(p: u64) -> Option<char> {
+    match p {
+        1..=26 => char::from_u32((p - 1) as u32 + 'a' as u32),
+        27..=52 => char::from_u32((p - 27) as u32 + 'A' as u32),
+        _ => None,
+    }
 }
 
-fn common_element(left: &str, right: &str) -> Option<char> {
-    let l: HashSet<char> = left.chars().collect();
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+struct ItemSet(u64);
 
-    let mut it = right.chars().filter(|c| l.contains(c));
-    let common_element = it.next();
-    for next_element in it {
-        assert_eq!(Some(next_element), common_element);
+impl ItemSet {
+    fn from_items(s: &str) -> Self {
+        let mut set = Self::default();
+        for c in s.chars() {
+            set.insert(c);
+        }
+        set
+    }
+
+    fn insert(&mut self, c: char) -> bool {
+        match get_priority(c) {
+            Some(p) => {
+                self.0 |= 1 << (p - 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn contains(self, c: char) -> bool {
+        get_priority(c).is_some_and(|p| self.0 & (1 << (p - 1)) != 0)
+    }
+
+    fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    fn intersect_all(sets: impl IntoIterator<Item = Self>) -> Self {
+        sets.into_iter()
+            .reduce(Self::intersection)
+            .unwrap_or_default()
+    }
+
+    fn len(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    fn items(self) -> impl Iterator<Item = char> {
+        (1..=52)
+            .filter(move |p| self.0 & (1 << (p - 1)) != 0)
+            .filter_map(item_from_priority)
+    }
+
+    fn single_item(self) -> Option<char> {
+        if self.len() == 1 {
+            self.items().next()
+        } else {
+            None
+        }
     }
-    common_element
 }
 
-fn common_element2(e0: &str, e1: &str, e2: &str) -> Option<char> {
-    let e0: HashSet<char> = e0.chars().collect();
-    let e1: HashSet<char> = e1.chars().collect();
-    let e2: HashSet<char> = e2.chars().collect();
-    _common_element2(&e0, &e1, &e2)
+fn split_elements(s: &str) -> Option<(&str, &str)> {
+    let nitems = s.chars().count();
+    if !nitems.is_multiple_of(2) {
+        return None;
+    }
+    let split = s.char_indices().nth(nitems / 2).map_or(s.len(), |(i, _)| i);
+    Some(s.split_at(split))
 }
 
-fn _common_element2(e0: &HashSet<char>, e1: &HashSet<char>, e2: &HashSet<char>) -> Option<char> {
-    let e01: HashSet<char> = e0.intersection(e1).copied().collect();
+fn rucksack_common_element(s: &str) -> Option<char> {
+    let (left, right) = split_elements(s)?;
+    common_element(left, right)
+}
 
-    let mut it = e1.intersection(e2).filter(|c| e01.contains(c));
-    let common_element = it.next();
-    for next_element in it {
-        assert_eq!(Some(next_element), common_element);
-    }
-    common_element.copied()
+fn common_element(left: &str, right: &str) -> Option<char> {
+    ItemSet::from_items(left)
+        .intersection(ItemSet::from_items(right))
+        .single_item()
 }
 
-fn simple_iterator2(p: &Path) -> Result<u64, Error> {
+fn common_group_element(elves: &[&str]) -> Option<char> {
+    ItemSet::intersect_all(elves.iter().map(|e| ItemSet::from_items(e))).single_item()
+}
+
+fn simple_iterator2(p: &Path, group_size: usize) -> Result<u64, Error> {
     let buf = fs::read_to_string(p)?;
 
     let total_priority = buf
         .split('\n')
-        .chunks(3)
+        .chunks(group_size)
         .into_iter()
         .map(|chunk| {
             let elfs: SmallVec<[&str; 3]> = chunk.collect();
-            if elfs.len() == 3 {
-                common_element2(elfs[0], elfs[1], elfs[2])
+            if elfs.len() == group_size {
+                common_group_element(&elfs)
                     .and_then(get_priority)
                     .unwrap_or(0)
             } else {
@@ -144,21 +248,26 @@ fn simple_iterator2(p: &Path) -> Result<u64, Error> {
     Ok(total_priority)
 }
 
-fn use_bufreader2(p: &Path) -> Result<u64, Error> {
+fn use_bufreader2(p: &Path, group_size: usize) -> Result<u64, Error> {
     let f = fs::File::open(p)?;
     let it = BufReadIter2::new(f);
-    let total_priority = it
-        .chunks(3)
-        .into_iter()
-        .try_fold(0, |total_priority, chunk| {
-            let result: Result<SmallVec<[HashSet<char>; 3]>, Error> = chunk.collect();
-            result.map(|elfs| {
-                total_priority
-                    + _common_element2(&elfs[0], &elfs[1], &elfs[2])
-                        .and_then(get_priority)
-                        .unwrap_or(0)
-            })
-        })?;
+    let total_priority =
+        it.chunks(group_size)
+            .into_iter()
+            .try_fold(0, |total_priority, chunk| {
+                let result: Result<SmallVec<[ItemSet; 3]>, Error> = chunk.collect();
+                result.map(|elfs| {
+                    if elfs.len() == group_size {
+                        total_priority
+                            + ItemSet::intersect_all(elfs)
+                                .single_item()
+                                .and_then(get_priority)
+                                .unwrap_or(0)
+                    } else {
+                        total_priority
+                    }
+                })
+            })?;
     Ok(total_priority)
 }
 
@@ -177,12 +286,12 @@ impl<T: Read> BufReadIter2<T> {
 }
 
 impl<T: Read> Iterator for BufReadIter2<T> {
-    type Item = Result<HashSet<char>, Error>;
+    type Item = Result<ItemSet, Error>;
     fn next(&mut self) -> Option<Self::Item> {
         match self.reader.read_line(&mut self.line) {
             Ok(0) => None,
             Ok(_) => {
-                let h: HashSet<char> = self.line.trim().chars().collect();
+                let h = ItemSet::from_items(self.line.trim());
                 self.line.clear();
                 Some(Ok(h))
             }
@@ -191,6 +300,462 @@ impl<T: Read> Iterator for BufReadIter2<T> {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Issue {
+    OddLength(usize),
+    InvalidItems(String),
+    NoCommonItem,
+    MultipleCommonItems(String),
+    NoBadge,
+    MultipleBadges(String),
+    IncompleteGroup(usize),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OddLength(len) => write!(f, "odd number of items ({len})"),
+            Self::InvalidItems(items) => write!(f, "non-letter items {items:?}"),
+            Self::NoCommonItem => write!(f, "no item in both compartments"),
+            Self::MultipleCommonItems(items) => {
+                write!(f, "multiple items in both compartments {items:?}")
+            }
+            Self::NoBadge => write!(f, "group has no common badge"),
+            Self::MultipleBadges(items) => write!(f, "group has multiple badges {items:?}"),
+            Self::IncompleteGroup(size) => write!(f, "incomplete group of {size} elves"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Diagnostic {
+    line: usize,
+    issue: Issue,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.issue)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Report {
+    total_priority: u64,
+    total_badge_priority: u64,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn rucksack_issues(s: &str) -> SmallVec<[Issue; 2]> {
+    let mut issues = SmallVec::new();
+    let invalid: String = s.chars().filter(|c| get_priority(*c).is_none()).collect();
+    if !invalid.is_empty() {
+        issues.push(Issue::InvalidItems(invalid));
+    }
+    match split_elements(s) {
+        None => issues.push(Issue::OddLength(s.chars().count())),
+        Some((left, right)) => {
+            let common = ItemSet::from_items(left).intersection(ItemSet::from_items(right));
+            match common.len() {
+                0 => issues.push(Issue::NoCommonItem),
+                1 => {}
+                _ => issues.push(Issue::MultipleCommonItems(common.items().collect())),
+            }
+        }
+    }
+    issues
+}
+
+fn diagnose(buf: &str, group_size: usize) -> Report {
+    let mut report = Report::default();
+    let rucksacks: Vec<(usize, &str)> = buf
+        .lines()
+        .enumerate()
+        .map(|(i, s)| (i + 1, s.trim()))
+        .filter(|(_, s)| !s.is_empty())
+        .collect();
+
+    for (line, s) in &rucksacks {
+        let issues = rucksack_issues(s);
+        if issues.is_empty() {
+            report.total_priority += rucksack_common_element(s)
+                .and_then(get_priority)
+                .unwrap_or(0);
+        }
+        report.diagnostics.extend(
+            issues
+                .into_iter()
+                .map(|issue| Diagnostic { line: *line, issue }),
+        );
+    }
+
+    for group in rucksacks.chunks(group_size.max(1)) {
+        let line = group[0].0;
+        if group.len() < group_size {
+            report.diagnostics.push(Diagnostic {
+                line,
+                issue: Issue::IncompleteGroup(group.len()),
+            });
+            continue;
+        }
+        let badges = ItemSet::intersect_all(group.iter().map(|(_, s)| ItemSet::from_items(s)));
+        match badges.len() {
+            0 => report.diagnostics.push(Diagnostic {
+                line,
+                issue: Issue::NoBadge,
+            }),
+            1 => {
+                report.total_badge_priority +=
+                    badges.single_item().and_then(get_priority).unwrap_or(0);
+            }
+            _ => report.diagnostics.push(Diagnostic {
+                line,
+                issue: Issue::MultipleBadges(badges.items().collect()),
+            }),
+        }
+    }
+    report.diagnostics.sort_by_key(|d| d.line);
+    report
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compartment {
+    Left,
+    Right,
+}
+
+impl Compartment {
+    fn other(self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+}
+
+impl fmt::Display for Compartment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Left => write!(f, "left"),
+            Self::Right => write!(f, "right"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Rucksack {
+    left: String,
+    right: String,
+}
+
+impl Rucksack {
+    fn new(s: &str) -> Self {
+        let split = s.chars().count() / 2;
+        Self {
+            left: s.chars().take(split).collect(),
+            right: s.chars().skip(split).collect(),
+        }
+    }
+
+    fn compartment(&self, compartment: Compartment) -> &str {
+        match compartment {
+            Compartment::Left => &self.left,
+            Compartment::Right => &self.right,
+        }
+    }
+
+    fn compartment_mut(&mut self, compartment: Compartment) -> &mut String {
+        match compartment {
+            Compartment::Left => &mut self.left,
+            Compartment::Right => &mut self.right,
+        }
+    }
+
+    fn items(&self) -> ItemSet {
+        ItemSet::from_items(&self.left).union(ItemSet::from_items(&self.right))
+    }
+
+    fn count(&self, item: char, compartment: Compartment) -> usize {
+        self.compartment(compartment)
+            .chars()
+            .filter(|c| *c == item)
+            .count()
+    }
+
+    fn location(&self, item: char) -> Option<Compartment> {
+        if self.left.contains(item) {
+            Some(Compartment::Left)
+        } else if self.right.contains(item) {
+            Some(Compartment::Right)
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self, item: char, compartment: Compartment) -> bool {
+        let s = self.compartment_mut(compartment);
+        match s.find(item) {
+            Some(i) => {
+                s.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn put(&mut self, item: char, compartment: Compartment) {
+        self.compartment_mut(compartment).push(item);
+    }
+
+    fn separation(&self) -> Option<ItemSet> {
+        if self.left.chars().count() != self.right.chars().count()
+            || !self
+                .left
+                .chars()
+                .chain(self.right.chars())
+                .all(|c| get_priority(c).is_some())
+        {
+            return None;
+        }
+        let half = self.left.chars().count();
+        let mut best: Vec<Option<(usize, ItemSet)>> = vec![None; half + 1];
+        best[0] = Some((0, ItemSet::default()));
+        for item in self.items().items() {
+            let left = self.count(item, Compartment::Left);
+            let total = left + self.count(item, Compartment::Right);
+            for size in (total..=half).rev() {
+                let Some((kept, mut set)) = best[size - total] else {
+                    continue;
+                };
+                if best[size].is_none_or(|(k, _)| k < kept + left) {
+                    set.insert(item);
+                    best[size] = Some((kept + left, set));
+                }
+            }
+        }
+        best[half].map(|(_, set)| set)
+    }
+}
+
+impl fmt::Display for Rucksack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}|{}", self.left, self.right)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ItemMove {
+    item: char,
+    from: (usize, Compartment),
+    to: (usize, Compartment),
+    exchanged: char,
+}
+
+impl fmt::Display for ItemMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "swap {} from elf {} {} with {} from elf {} {}",
+            self.item,
+            self.from.0 + 1,
+            self.from.1,
+            self.exchanged,
+            self.to.0 + 1,
+            self.to.1
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Plan {
+    moves: Vec<ItemMove>,
+    rucksacks: Vec<Rucksack>,
+    unseparated_rucksacks: Vec<usize>,
+    unresolved_groups: Vec<std::ops::Range<usize>>,
+}
+
+impl Plan {
+    fn apply(&mut self, item_move: ItemMove) {
+        let (from, from_compartment) = item_move.from;
+        let (to, to_compartment) = item_move.to;
+        if self.rucksacks[from].take(item_move.item, from_compartment)
+            && self.rucksacks[to].take(item_move.exchanged, to_compartment)
+        {
+            self.rucksacks[to].put(item_move.item, to_compartment);
+            self.rucksacks[from].put(item_move.exchanged, from_compartment);
+            self.moves.push(item_move);
+        }
+    }
+
+    fn separate_compartments(&mut self, elf: usize) {
+        let rucksack = &self.rucksacks[elf];
+        let Some(left_items) = rucksack.separation() else {
+            self.unseparated_rucksacks.push(elf);
+            return;
+        };
+        let outgoing = rucksack.left.chars().filter(|c| !left_items.contains(*c));
+        let incoming = rucksack.right.chars().filter(|c| left_items.contains(*c));
+        let moves: Vec<ItemMove> = outgoing
+            .zip(incoming)
+            .map(|(item, exchanged)| ItemMove {
+                item,
+                from: (elf, Compartment::Left),
+                to: (elf, Compartment::Right),
+                exchanged,
+            })
+            .collect();
+        for item_move in moves {
+            self.apply(item_move);
+        }
+    }
+
+    fn total_count(&self, elf: usize, item: char) -> usize {
+        let rucksack = &self.rucksacks[elf];
+        rucksack.count(item, Compartment::Left) + rucksack.count(item, Compartment::Right)
+    }
+
+    fn exchange(
+        &self,
+        group: std::ops::Range<usize>,
+        item: char,
+        from: (usize, Compartment),
+        to: (usize, Compartment),
+        badges: ItemSet,
+    ) -> Option<ItemMove> {
+        let receiver = &self.rucksacks[to.0];
+        if receiver.compartment(to.1.other()).contains(item) {
+            return None;
+        }
+        let donor = &self.rucksacks[from.0];
+        let exchanged = receiver.compartment(to.1).chars().find(|c| {
+            *c != item
+                && !badges.contains(*c)
+                && !donor.compartment(from.1.other()).contains(*c)
+                && group
+                    .clone()
+                    .filter(|i| *i != from.0)
+                    .any(|i| !self.rucksacks[i].items().contains(*c))
+        })?;
+        Some(ItemMove {
+            item,
+            from,
+            to,
+            exchanged,
+        })
+    }
+
+    fn fix_badge(&mut self, group: std::ops::Range<usize>) {
+        let badges = ItemSet::intersect_all(group.clone().map(|i| self.rucksacks[i].items()));
+        if badges.len() == 1 {
+            return;
+        }
+        if badges.len() > 1 {
+            let keep = badges.items().next();
+            for badge in badges.items().filter(|b| Some(*b) != keep) {
+                let Some(from) = group.clone().min_by_key(|i| self.total_count(*i, badge)) else {
+                    continue;
+                };
+                for compartment in [Compartment::Left, Compartment::Right] {
+                    for _ in 0..self.rucksacks[from].count(badge, compartment) {
+                        let item_move = group.clone().filter(|i| *i != from).find_map(|to| {
+                            let to_compartment = self.rucksacks[to].location(badge)?;
+                            self.exchange(
+                                group.clone(),
+                                badge,
+                                (from, compartment),
+                                (to, to_compartment),
+                                badges,
+                            )
+                        });
+                        let Some(item_move) = item_move else {
+                            return;
+                        };
+                        self.apply(item_move);
+                    }
+                }
+            }
+            return;
+        }
+
+        let candidate = (1..=52)
+            .filter_map(item_from_priority)
+            .filter(|item| {
+                group
+                    .clone()
+                    .map(|i| self.total_count(i, *item))
+                    .sum::<usize>()
+                    >= group.len()
+            })
+            .min_by_key(|item| {
+                group
+                    .clone()
+                    .filter(|i| !self.rucksacks[*i].items().contains(*item))
+                    .count()
+            });
+        let Some(badge) = candidate else {
+            return;
+        };
+        let mut badges = ItemSet::default();
+        badges.insert(badge);
+        for to in group.clone() {
+            if self.rucksacks[to].items().contains(badge) {
+                continue;
+            }
+            let Some(from) = group.clone().find(|i| self.total_count(*i, badge) > 1) else {
+                return;
+            };
+            let Some(from_compartment) = self.rucksacks[from].location(badge) else {
+                return;
+            };
+            let item_move = [Compartment::Left, Compartment::Right]
+                .into_iter()
+                .find_map(|to_compartment| {
+                    self.exchange(
+                        group.clone(),
+                        badge,
+                        (from, from_compartment),
+                        (to, to_compartment),
+                        badges,
+                    )
+                });
+            let Some(item_move) = item_move else {
+                return;
+            };
+            self.apply(item_move);
+        }
+    }
+}
+
+fn plan_reorganisation(buf: &str, group_size: Option<usize>) -> Plan {
+    let mut plan = Plan {
+        rucksacks: buf
+            .lines()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Rucksack::new)
+            .collect(),
+        ..Plan::default()
+    };
+    for elf in 0..plan.rucksacks.len() {
+        plan.separate_compartments(elf);
+    }
+    if let Some(group_size) = group_size.filter(|g| *g > 1) {
+        let nelves = plan.rucksacks.len();
+        for start in (0..nelves).step_by(group_size) {
+            if start + group_size <= nelves {
+                let group = start..start + group_size;
+                plan.fix_badge(group.clone());
+                let badges =
+                    ItemSet::intersect_all(group.clone().map(|i| plan.rucksacks[i].items()));
+                if badges.len() != 1 {
+                    plan.unresolved_groups.push(group);
+                }
+            }
+        }
+    }
+    plan
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
@@ -217,7 +782,7 @@ mod tests {
             ("ttgJtRGJQctTZtZT", 't'),
             ("CrZsJsPPZsGzwwsLwLmpwMDw", 's'),
         ] {
-            let (left, right) = split_elements(s);
+            let (left, right) = split_elements(s).unwrap();
             let common = common_element(left, right);
             assert_eq!(common.unwrap(), c);
             priorities += get_priority(c).unwrap_or(0);
@@ -228,38 +793,56 @@ mod tests {
     #[test]
     fn test_common_element2() {
         assert_eq!(
-            common_element2(
+            common_group_element(&[
                 "vJrwpWtwJgWrhcsFMMfFFhFp",
                 "jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL",
                 "PmmdzqPrVvPwwTWBwg"
-            ),
+            ]),
             Some('r')
         );
         assert_eq!(
-            common_element2(
+            common_group_element(&[
                 "wMqvLMZHhHMvwLHjbvcjnnSBnvTQFn",
                 "ttgJtRGJQctTZtZT",
                 "CrZsJsPPZsGzwwsLwLmpwMDw"
-            ),
+            ]),
             Some('Z')
         );
-        let p0 = common_element2(
+        let p0 = common_group_element(&[
             "vJrwpWtwJgWrhcsFMMfFFhFp",
             "jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL",
             "PmmdzqPrVvPwwTWBwg",
-        )
+        ])
         .and_then(get_priority)
         .unwrap_or(0);
-        let p1 = common_element2(
+        let p1 = common_group_element(&[
             "wMqvLMZHhHMvwLHjbvcjnnSBnvTQFn",
             "ttgJtRGJQctTZtZT",
             "CrZsJsPPZsGzwwsLwLmpwMDw",
-        )
+        ])
         .and_then(get_priority)
         .unwrap_or(0);
         assert_eq!(p0 + p1, 70);
     }
 
+    #[test]
+    fn test_item_set() {
+        let set = ItemSet::from_items("vJrwpWtwJgWrhcsFMMfFFhFp");
+        assert_eq!(set.items().collect::<String>(), "cfghprstvwFJMW");
+        assert_eq!(set.len(), 14);
+        assert_eq!(ItemSet::intersect_all([]), ItemSet::default());
+        for p in 1..=52 {
+            assert_eq!(item_from_priority(p).and_then(get_priority), Some(p));
+        }
+        let sets = ["abcX", "bcXY", "cXYZ", "XcQ"].map(ItemSet::from_items);
+        let common: String = ItemSet::intersect_all(sets).items().collect();
+        assert_eq!(common, "cX");
+        assert_eq!(
+            common_group_element(&["abc", "bcd", "cde", "cef", "cfg"]),
+            Some('c')
+        );
+    }
+
     #[test]
     fn test_result0() -> Result<(), Error> {
         let buf = include_str!("../input.txt");
@@ -267,9 +850,9 @@ mod tests {
         let total_priority: u64 = buf
             .split('\n')
             .map(|s| {
-                let (left, right) = split_elements(s);
-                let common = common_element(left, right);
-                common.and_then(get_priority).unwrap_or(0)
+                rucksack_common_element(s)
+                    .and_then(get_priority)
+                    .unwrap_or(0)
             })
             .sum();
         assert_eq!(total_priority, 7967);
@@ -299,7 +882,7 @@ mod tests {
             .map(|chunk| {
                 let elfs: SmallVec<[&str; 3]> = chunk.collect();
                 if elfs.len() == 3 {
-                    common_element2(elfs[0], elfs[1], elfs[2])
+                    common_group_element(&elfs)
                         .and_then(get_priority)
                         .unwrap_or(0)
                 } else {
@@ -321,10 +904,11 @@ mod tests {
             it.chunks(3)
                 .into_iter()
                 .try_fold(0, |total_priority, chunk| {
-                    let result: Result<SmallVec<[HashSet<char>; 3]>, Error> = chunk.collect();
+                    let result: Result<SmallVec<[ItemSet; 3]>, Error> = chunk.collect();
                     result.map(|elfs| {
                         total_priority
-                            + _common_element2(&elfs[0], &elfs[1], &elfs[2])
+                            + ItemSet::intersect_all(elfs)
+                                .single_item()
                                 .and_then(get_priority)
                                 .unwrap_or(0)
                     })
@@ -332,4 +916,126 @@ mod tests {
         assert_eq!(total_priority, 2716);
         Ok(())
     }
+
+    #[test]
+    fn test_diagnose() {
+        let buf = "vJrwpWtwJgWrhcsFMMfFFhFp\n\
+                   jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL\n\
+                   PmmdzqPrVvPwwTWBwgx\n\
+                   abcabc\n\
+                   a1b2\n";
+        let report = diagnose(buf, 3);
+        assert_eq!(report.total_priority, 16 + 38);
+        assert_eq!(report.total_badge_priority, 18);
+        assert_eq!(
+            report.diagnostics,
+            vec![
+                Diagnostic {
+                    line: 3,
+                    issue: Issue::OddLength(19)
+                },
+                Diagnostic {
+                    line: 4,
+                    issue: Issue::MultipleCommonItems("abc".into())
+                },
+                Diagnostic {
+                    line: 4,
+                    issue: Issue::IncompleteGroup(2)
+                },
+                Diagnostic {
+                    line: 5,
+                    issue: Issue::InvalidItems("12".into())
+                },
+                Diagnostic {
+                    line: 5,
+                    issue: Issue::NoCommonItem
+                },
+            ]
+        );
+
+        assert_eq!(
+            rucksack_issues("ab€€").into_vec(),
+            vec![Issue::InvalidItems("€€".into()), Issue::NoCommonItem]
+        );
+        assert_eq!(
+            rucksack_issues("ab€").into_vec(),
+            vec![Issue::InvalidItems("€".into()), Issue::OddLength(3)]
+        );
+
+        let report = diagnose(include_str!("../input.txt"), 3);
+        assert!(report.diagnostics.is_empty());
+        assert_eq!(report.total_priority, 7967);
+        assert_eq!(report.total_badge_priority, 2716);
+    }
+
+    #[test]
+    fn test_plan_reorganisation() {
+        let plan = plan_reorganisation("vJrwpWtwJgWrhcsFMMfFFhFp\nabcaBc\naaab\n", None);
+        assert_eq!(
+            plan.moves,
+            vec![
+                ItemMove {
+                    item: 'v',
+                    from: (0, Compartment::Left),
+                    to: (0, Compartment::Right),
+                    exchanged: 'p'
+                },
+                ItemMove {
+                    item: 'c',
+                    from: (1, Compartment::Left),
+                    to: (1, Compartment::Right),
+                    exchanged: 'a'
+                },
+            ]
+        );
+        assert_eq!(plan.rucksacks[1].to_string(), "aba|Bcc");
+        assert_eq!(
+            plan.moves[0].to_string(),
+            "swap v from elf 1 left with p from elf 1 right"
+        );
+        assert_eq!(plan.unseparated_rucksacks, vec![2]);
+        assert_eq!(plan.rucksacks[2].to_string(), "aa|ab");
+
+        let plan = plan_reorganisation("abcdef\nabghij\nabklmn\n", Some(3));
+        assert_eq!(plan.moves.len(), 1);
+        let badges = ItemSet::intersect_all(plan.rucksacks.iter().map(|r| r.items()));
+        assert_eq!(badges.len(), 1);
+        assert!(plan.unresolved_groups.is_empty());
+
+        let plan = plan_reorganisation("aacd\nefgh\nijkl\n", Some(3));
+        assert_eq!(plan.moves.len(), 0);
+        assert_eq!(plan.unresolved_groups, vec![0..3]);
+        let plan = plan_reorganisation("aaadef\nbcgxyz\nhijklm\n", Some(3));
+        assert_eq!(plan.moves.len(), 2);
+        assert_eq!(plan.rucksacks[0].to_string(), "abh|def");
+        assert_eq!(plan.rucksacks[1].to_string(), "cga|xyz");
+        let badges = ItemSet::intersect_all(plan.rucksacks.iter().map(|r| r.items()));
+        assert_eq!(badges.items().collect::<String>(), "a");
+        assert!(plan.unresolved_groups.is_empty());
+
+        let buf = include_str!("../input.txt");
+        let plan = plan_reorganisation(buf, Some(3));
+        assert!(plan.unseparated_rucksacks.is_empty());
+        assert!(plan.unresolved_groups.is_empty());
+        for rucksack in &plan.rucksacks {
+            assert_eq!(rucksack.left.len(), rucksack.right.len());
+            assert_eq!(
+                ItemSet::from_items(&rucksack.left)
+                    .intersection(ItemSet::from_items(&rucksack.right))
+                    .len(),
+                0
+            );
+        }
+        let buf: String = plan
+            .rucksacks
+            .iter()
+            .map(|r| format!("{}{}\n", r.left, r.right))
+            .collect();
+        let report = diagnose(&buf, 3);
+        assert_eq!(report.diagnostics.len(), plan.rucksacks.len());
+        assert!(report
+            .diagnostics
+            .iter()
+            .all(|d| d.issue == Issue::NoCommonItem));
+    }
 }
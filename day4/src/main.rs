@@ -1,18 +1,103 @@
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use clap::Parser;
 use smallvec::SmallVec;
+use std::fmt;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Parser)]
 struct Input {
     input: PathBuf,
+    /// print the sections between the lowest and highest assignment covered by nobody
+    #[arg(long)]
+    uncovered: bool,
+    /// print the sections covered by at least this many elves
+    #[arg(long)]
+    covered_by: Option<usize>,
+    /// report which parts of a range of sections, e.g. `10-20`, are covered
+    #[arg(long)]
+    check: Option<String>,
+    /// compare every assignment against all others in the file
+    #[arg(long)]
+    sweep: bool,
+    /// classify how the two assignments on each line relate
+    #[arg(long)]
+    classify: bool,
 }
 
 fn main() -> Result<(), Error> {
     let opts = Input::parse();
 
+    if opts.uncovered || opts.covered_by.is_some() || opts.check.is_some() {
+        let buf = fs::read_to_string(&opts.input)?;
+        let pairs = parse_range_pairs(&buf);
+        let first: IntervalSet = pairs.iter().map(|(r0, _)| *r0).collect();
+        let second: IntervalSet = pairs.iter().map(|(_, r1)| *r1).collect();
+        let covered = first.union(&second);
+        if opts.uncovered {
+            if let Some(bound) = covered.bounds() {
+                let uncovered = covered.complement(bound);
+                println!("uncovered {uncovered} ({} sections)", uncovered.len());
+            }
+        }
+        if let Some(k) = opts.covered_by {
+            if k == 0 {
+                return Err(format_err!("--covered-by needs at least 1 elf"));
+            }
+            let ranges: Vec<Range> = pairs.iter().flat_map(|(r0, r1)| [*r0, *r1]).collect();
+            let at_least = IntervalSet::covered_at_least(&ranges, k);
+            let exactly = at_least.difference(&IntervalSet::covered_at_least(&ranges, k + 1));
+            println!(
+                "covered by {k} or more {at_least} ({} sections)",
+                at_least.len()
+            );
+            println!(
+                "covered by exactly {k} {exactly} ({} sections)",
+                exactly.len()
+            );
+        }
+        if let Some(check) = &opts.check {
+            let r: Range = check.parse()?;
+            let query: IntervalSet = [r].into_iter().collect();
+            if covered.contains_range(r) {
+                println!("{r} is fully covered");
+            } else if covered.intersection(&query).is_empty() {
+                println!("{r} is not covered");
+            } else {
+                println!(
+                    "{r} is covered at {}, not at {}",
+                    covered.intersection(&query),
+                    query.difference(&covered)
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    if opts.classify {
+        let buf = fs::read_to_string(&opts.input)?;
+        let classes = classify_lines(&buf);
+        for (line, overlap) in &classes {
+            println!("line {line}: {overlap}");
+        }
+        let summary: OverlapSummary = classes.into_iter().map(|(_, o)| o).collect();
+        println!("{summary}");
+        return Ok(());
+    }
+
+    if opts.sweep {
+        let buf = fs::read_to_string(&opts.input)?;
+        let report = sweep_overlaps(&parse_range_pairs(&buf));
+        for elf in &report.elves {
+            println!("{elf}");
+        }
+        println!("maximum coverage depth {}", report.max_depth);
+        println!("deepest sections {}", report.deepest);
+        return Ok(());
+    }
+
     let overlaps = simple_iterator(&opts.input)?;
     println!("overlaps {overlaps}");
     assert_eq!(overlaps, 441);
@@ -54,9 +139,109 @@ fn simple_iterator2(p: &Path) -> Result<u64, Error> {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum RangeOverlaps {
-    NoOverlap,
-    PartialOverlap,
-    FullOverlap,
+    Disjoint,
+    Adjacent,
+    PartialLeft,
+    PartialRight,
+    Contains,
+    ContainedBy,
+    Equal,
+}
+
+impl RangeOverlaps {
+    const ALL: [Self; 7] = [
+        Self::Disjoint,
+        Self::Adjacent,
+        Self::PartialLeft,
+        Self::PartialRight,
+        Self::Contains,
+        Self::ContainedBy,
+        Self::Equal,
+    ];
+
+    fn is_full_overlap(self) -> bool {
+        matches!(self, Self::Contains | Self::ContainedBy | Self::Equal)
+    }
+
+    fn is_overlap(self) -> bool {
+        !matches!(self, Self::Disjoint | Self::Adjacent)
+    }
+}
+
+impl fmt::Display for RangeOverlaps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Self::Disjoint => "disjoint",
+            Self::Adjacent => "adjacent",
+            Self::PartialLeft => "partial-left",
+            Self::PartialRight => "partial-right",
+            Self::Contains => "contains",
+            Self::ContainedBy => "contained-by",
+            Self::Equal => "equal",
+        };
+        f.write_str(label)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct OverlapSummary([u64; 7]);
+
+impl OverlapSummary {
+    fn add(&mut self, overlap: RangeOverlaps) {
+        self.0[overlap as usize] += 1;
+    }
+
+    fn count(&self, overlap: RangeOverlaps) -> u64 {
+        self.0[overlap as usize]
+    }
+
+    fn full_overlaps(&self) -> u64 {
+        RangeOverlaps::ALL
+            .iter()
+            .filter(|o| o.is_full_overlap())
+            .map(|o| self.count(*o))
+            .sum()
+    }
+
+    fn overlaps(&self) -> u64 {
+        RangeOverlaps::ALL
+            .iter()
+            .filter(|o| o.is_overlap())
+            .map(|o| self.count(*o))
+            .sum()
+    }
+}
+
+impl FromIterator<RangeOverlaps> for OverlapSummary {
+    fn from_iter<T: IntoIterator<Item = RangeOverlaps>>(iter: T) -> Self {
+        let mut summary = Self::default();
+        for overlap in iter {
+            summary.add(overlap);
+        }
+        summary
+    }
+}
+
+impl fmt::Display for OverlapSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for overlap in RangeOverlaps::ALL {
+            writeln!(f, "{overlap}: {}", self.count(overlap))?;
+        }
+        writeln!(f, "fully overlapping: {}", self.full_overlaps())?;
+        write!(f, "overlapping: {}", self.overlaps())
+    }
+}
+
+fn classify_lines(buf: &str) -> Vec<(usize, RangeOverlaps)> {
+    buf.split('\n')
+        .enumerate()
+        .filter(|(_, s)| !s.is_empty())
+        .filter_map(|(i, s)| {
+            let (s0, s1) = s.split_once(',')?;
+            let overlap = get_range_overlap(convert_str_to_range(s0), convert_str_to_range(s1));
+            Some((i + 1, overlap))
+        })
+        .collect()
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -65,6 +250,329 @@ struct Range {
     end: u64,
 }
 
+impl Range {
+    fn len(self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+impl FromStr for Range {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| format_err!("Range {s:?} is not of the form start-end"))?;
+        let start: u64 = start.parse()?;
+        let end: u64 = end.parse()?;
+        if start > end {
+            return Err(format_err!("Range {s:?} ends before it starts"));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct IntervalSet(Vec<Range>);
+
+impl IntervalSet {
+    fn insert(&mut self, r: Range) {
+        let mut merged = r;
+        let mut ranges = Vec::with_capacity(self.0.len() + 1);
+        let mut inserted = false;
+        for &x in &self.0 {
+            if x.end.saturating_add(1) < merged.start {
+                ranges.push(x);
+            } else if merged.end.saturating_add(1) < x.start {
+                if !inserted {
+                    ranges.push(merged);
+                    inserted = true;
+                }
+                ranges.push(x);
+            } else {
+                merged = Range {
+                    start: merged.start.min(x.start),
+                    end: merged.end.max(x.end),
+                };
+            }
+        }
+        if !inserted {
+            ranges.push(merged);
+        }
+        self.0 = ranges;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn len(&self) -> u64 {
+        self.0.iter().map(|r| r.len()).sum()
+    }
+
+    fn bounds(&self) -> Option<Range> {
+        let first = self.0.first()?;
+        let last = self.0.last()?;
+        Some(Range {
+            start: first.start,
+            end: last.end,
+        })
+    }
+
+    fn contains_range(&self, r: Range) -> bool {
+        let i = self.0.partition_point(|x| x.end < r.start);
+        self.0
+            .get(i)
+            .is_some_and(|x| x.start <= r.start && r.end <= x.end)
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for &r in &other.0 {
+            result.insert(r);
+        }
+        result
+    }
+
+    fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.0.len() && j < other.0.len() {
+            let (a, b) = (self.0[i], other.0[j]);
+            let start = a.start.max(b.start);
+            let end = a.end.min(b.end);
+            if start <= end {
+                ranges.push(Range { start, end });
+            }
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        Self(ranges)
+    }
+
+    fn complement(&self, bound: Range) -> Self {
+        let mut ranges = Vec::new();
+        let mut start = bound.start;
+        for &r in &self.0 {
+            if r.end < bound.start {
+                continue;
+            }
+            if r.start > bound.end {
+                break;
+            }
+            if r.start > start {
+                ranges.push(Range {
+                    start,
+                    end: r.start - 1,
+                });
+            }
+            if r.end >= bound.end {
+                return Self(ranges);
+            }
+            start = r.end + 1;
+        }
+        ranges.push(Range {
+            start,
+            end: bound.end,
+        });
+        Self(ranges)
+    }
+
+    fn difference(&self, other: &Self) -> Self {
+        match self.bounds() {
+            Some(bound) => self.intersection(&other.complement(bound)),
+            None => Self::default(),
+        }
+    }
+
+    fn covered_at_least(ranges: &[Range], k: usize) -> Self {
+        let mut events: Vec<(u64, i64)> = ranges
+            .iter()
+            .flat_map(|r| [(r.start, 1), (r.end + 1, -1)])
+            .collect();
+        events.sort_unstable();
+        let mut result = Self::default();
+        let mut depth = 0;
+        let mut start = None;
+        for (section, delta) in events {
+            depth += delta;
+            match start {
+                None if depth >= k as i64 => start = Some(section),
+                Some(s) if depth < k as i64 => {
+                    result.insert(Range {
+                        start: s,
+                        end: section - 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
+impl FromIterator<Range> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = Range>>(iter: T) -> Self {
+        let mut set = Self::default();
+        for r in iter {
+            set.insert(r);
+        }
+        set
+    }
+}
+
+impl fmt::Display for IntervalSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, r) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{r}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ElfOverlap {
+    line: usize,
+    elf: usize,
+    range: Range,
+    overlaps: usize,
+    contained_by: usize,
+}
+
+impl fmt::Display for ElfOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} elf {} ({}): overlaps {}, contained by {}",
+            self.line, self.elf, self.range, self.overlaps, self.contained_by
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SweepReport {
+    elves: Vec<ElfOverlap>,
+    max_depth: usize,
+    deepest: IntervalSet,
+}
+
+struct Fenwick(Vec<usize>);
+
+impl Fenwick {
+    fn add(&mut self, index: usize) {
+        let mut i = index + 1;
+        while i < self.0.len() {
+            self.0[i] += 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    fn prefix_sum(&self, index: usize) -> usize {
+        let mut i = index;
+        let mut total = 0;
+        while i > 0 {
+            total += self.0[i];
+            i -= i & i.wrapping_neg();
+        }
+        total
+    }
+}
+
+fn max_depth(ranges: &[Range]) -> usize {
+    let mut events: Vec<(u64, i64)> = ranges
+        .iter()
+        .flat_map(|r| [(r.start, 1), (r.end + 1, -1)])
+        .collect();
+    events.sort_unstable();
+    let mut depth = 0;
+    let mut max_depth = 0;
+    for (_, delta) in events {
+        depth += delta;
+        max_depth = max_depth.max(depth);
+    }
+    max_depth as usize
+}
+
+fn sweep_overlaps(pairs: &[(Range, Range)]) -> SweepReport {
+    let ranges: Vec<Range> = pairs.iter().flat_map(|(r0, r1)| [*r0, *r1]).collect();
+    let n = ranges.len();
+
+    let mut starts: Vec<u64> = ranges.iter().map(|r| r.start).collect();
+    let mut ends: Vec<u64> = ranges.iter().map(|r| r.end).collect();
+    starts.sort_unstable();
+    ends.sort_unstable();
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_unstable_by(|&a, &b| {
+        let (ra, rb) = (ranges[a], ranges[b]);
+        ra.start.cmp(&rb.start).then(rb.end.cmp(&ra.end))
+    });
+    let mut contained_by = vec![0; n];
+    let mut processed = Fenwick(vec![0; n + 1]);
+    let mut count = 0;
+    for group in order.chunk_by(|&a, &b| ranges[a] == ranges[b]) {
+        let r = ranges[group[0]];
+        let rank = ends.partition_point(|e| *e < r.end);
+        let reaching = count - processed.prefix_sum(rank);
+        for &i in group {
+            contained_by[i] = reaching + group.len() - 1;
+        }
+        for &i in group {
+            processed.add(ends.partition_point(|e| *e < ranges[i].end));
+            count += 1;
+        }
+    }
+
+    let elves = ranges
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let before = ends.partition_point(|e| *e < r.start);
+            let after = n - starts.partition_point(|s| *s <= r.end);
+            ElfOverlap {
+                line: i / 2 + 1,
+                elf: i % 2 + 1,
+                range: *r,
+                overlaps: n - 1 - before - after,
+                contained_by: contained_by[i],
+            }
+        })
+        .collect();
+
+    let max_depth = max_depth(&ranges);
+    let deepest = if max_depth > 0 {
+        IntervalSet::covered_at_least(&ranges, max_depth)
+    } else {
+        IntervalSet::default()
+    };
+    SweepReport {
+        elves,
+        max_depth,
+        deepest,
+    }
+}
+
+fn parse_range_pairs(buf: &str) -> Vec<(Range, Range)> {
+    buf.split('\n')
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| s.split_once(','))
+        .map(|(s0, s1)| (convert_str_to_range(s0), convert_str_to_range(s1)))
+        .collect()
+}
+
 fn convert_str_to_range(s: &str) -> Range {
     let v: SmallVec<[&str; 2]> = s.split('-').take(2).collect();
     let start: u64 = v[0].parse().ok().unwrap_or(0);
@@ -74,17 +582,20 @@ fn convert_str_to_range(s: &str) -> Range {
 }
 
 fn get_range_overlap(r0: Range, r1: Range) -> RangeOverlaps {
-    let (lower, upper) = if r0.start <= r1.start {
-        (r0, r1)
+    if r0 == r1 {
+        RangeOverlaps::Equal
+    } else if r0.end + 1 == r1.start || r1.end + 1 == r0.start {
+        RangeOverlaps::Adjacent
+    } else if r0.end < r1.start || r1.end < r0.start {
+        RangeOverlaps::Disjoint
+    } else if r0.start <= r1.start && r1.end <= r0.end {
+        RangeOverlaps::Contains
+    } else if r1.start <= r0.start && r0.end <= r1.end {
+        RangeOverlaps::ContainedBy
+    } else if r0.start < r1.start {
+        RangeOverlaps::PartialLeft
     } else {
-        (r1, r0)
-    };
-    if lower.end < upper.start {
-        RangeOverlaps::NoOverlap
-    } else if lower.start < upper.start && lower.end < upper.end {
-        RangeOverlaps::PartialOverlap
-    } else {
-        RangeOverlaps::FullOverlap
+        RangeOverlaps::PartialRight
     }
 }
 
@@ -92,14 +603,14 @@ fn check_if_ranges_fully_overlap(s: &str) -> bool {
     let v: SmallVec<[&str; 2]> = s.split(',').collect();
     let r0 = convert_str_to_range(v[0]);
     let r1 = convert_str_to_range(v[1]);
-    get_range_overlap(r0, r1) == RangeOverlaps::FullOverlap
+    get_range_overlap(r0, r1).is_full_overlap()
 }
 
 fn check_if_ranges_overlap(s: &str) -> bool {
     let v: SmallVec<[&str; 2]> = s.split(',').collect();
     let r0 = convert_str_to_range(v[0]);
     let r1 = convert_str_to_range(v[1]);
-    get_range_overlap(r0, r1) != RangeOverlaps::NoOverlap
+    get_range_overlap(r0, r1).is_overlap()
 }
 
 #[cfg(test)]
@@ -131,4 +642,117 @@ mod tests {
         assert!(check_if_ranges_overlap("6-6,4-6"));
         assert!(check_if_ranges_overlap("2-6,4-8"));
     }
+
+    #[test]
+    fn test_range_from_str() {
+        assert_eq!(
+            "10-20".parse::<Range>().unwrap(),
+            Range { start: 10, end: 20 }
+        );
+        assert!("foo".parse::<Range>().is_err());
+        assert!("9-3".parse::<Range>().is_err());
+        assert!("1-x".parse::<Range>().is_err());
+    }
+
+    #[test]
+    fn test_interval_set() {
+        let r = |start, end| Range { start, end };
+        let set: IntervalSet = [r(2, 4), r(6, 8), r(5, 5), r(12, 14)].into_iter().collect();
+        assert_eq!(set.0, [r(2, 8), r(12, 14)]);
+        assert_eq!(set.to_string(), "2-8,12-14");
+        assert_eq!(set.len(), 10);
+        assert!(set.contains_range(r(8, 8)));
+        assert!(!set.contains_range(r(9, 9)));
+        assert!(set.contains_range(r(3, 7)));
+        assert!(!set.contains_range(r(7, 12)));
+
+        let other: IntervalSet = [r(4, 13)].into_iter().collect();
+        assert_eq!(set.union(&other).0, [r(2, 14)]);
+        assert_eq!(set.intersection(&other).0, [r(4, 8), r(12, 13)]);
+        assert_eq!(set.difference(&other).0, [r(2, 3), r(14, 14)]);
+        assert_eq!(other.difference(&set).0, [r(9, 11)]);
+        assert_eq!(set.complement(r(0, 20)).0, [r(0, 1), r(9, 11), r(15, 20)]);
+        assert_eq!(set.complement(r(3, 13)).0, [r(9, 11)]);
+        assert!(set.complement(r(4, 7)).is_empty());
+        assert!(IntervalSet::default().difference(&set).is_empty());
+
+        let pairs = parse_range_pairs("2-4,6-8\n2-3,4-5\n5-7,7-9\n2-8,3-7\n6-6,4-6\n2-6,4-8\n");
+        let ranges: Vec<Range> = pairs.iter().flat_map(|(r0, r1)| [*r0, *r1]).collect();
+        let covered: IntervalSet = ranges.iter().copied().collect();
+        assert_eq!(covered.0, [r(2, 9)]);
+        assert_eq!(IntervalSet::covered_at_least(&ranges, 7).0, [r(4, 6)]);
+        assert_eq!(IntervalSet::covered_at_least(&ranges, 1), covered);
+    }
+
+    #[test]
+    fn test_sweep_overlaps() {
+        let r = |start, end| Range { start, end };
+        let pairs = parse_range_pairs("2-4,6-8\n2-3,4-5\n5-7,7-9\n2-8,3-7\n6-6,4-6\n2-6,4-8\n");
+        let report = sweep_overlaps(&pairs);
+        assert_eq!(report.max_depth, 8);
+        assert_eq!(report.deepest.0, [r(6, 6)]);
+
+        let ranges: Vec<Range> = pairs.iter().flat_map(|(r0, r1)| [*r0, *r1]).collect();
+        for (i, elf) in report.elves.iter().enumerate() {
+            let overlaps = ranges
+                .iter()
+                .enumerate()
+                .filter(|(j, x)| *j != i && get_range_overlap(**x, elf.range).is_overlap())
+                .count();
+            let contained_by = ranges
+                .iter()
+                .enumerate()
+                .filter(|(j, x)| *j != i && x.start <= elf.range.start && elf.range.end <= x.end)
+                .count();
+            assert_eq!(elf.overlaps, overlaps);
+            assert_eq!(elf.contained_by, contained_by);
+        }
+        assert_eq!(
+            report.elves[8],
+            ElfOverlap {
+                line: 5,
+                elf: 1,
+                range: r(6, 6),
+                overlaps: 7,
+                contained_by: 7
+            }
+        );
+
+        let report = sweep_overlaps(&[(r(1, 3), r(1, 3))]);
+        assert_eq!(report.elves[0].contained_by, 1);
+        assert_eq!(report.elves[1].contained_by, 1);
+        assert_eq!(report.max_depth, 2);
+    }
+
+    #[test]
+    fn test_get_range_overlap() {
+        let r = |start, end| Range { start, end };
+        for (r0, r1, expected) in [
+            (r(2, 4), r(6, 8), RangeOverlaps::Disjoint),
+            (r(2, 3), r(4, 5), RangeOverlaps::Adjacent),
+            (r(4, 5), r(2, 3), RangeOverlaps::Adjacent),
+            (r(5, 7), r(7, 9), RangeOverlaps::PartialLeft),
+            (r(4, 8), r(2, 6), RangeOverlaps::PartialRight),
+            (r(2, 8), r(3, 7), RangeOverlaps::Contains),
+            (r(6, 6), r(4, 6), RangeOverlaps::ContainedBy),
+            (r(3, 5), r(3, 5), RangeOverlaps::Equal),
+        ] {
+            assert_eq!(get_range_overlap(r0, r1), expected);
+        }
+
+        let buf = "2-4,6-8\n2-3,4-5\n5-7,7-9\n2-8,3-7\n6-6,4-6\n2-6,4-8\n";
+        let classes = classify_lines(buf);
+        assert_eq!(classes[4], (5, RangeOverlaps::ContainedBy));
+        let summary: OverlapSummary = classes.into_iter().map(|(_, o)| o).collect();
+        assert_eq!(summary.count(RangeOverlaps::Adjacent), 1);
+        assert_eq!(summary.full_overlaps(), 2);
+        assert_eq!(summary.overlaps(), 4);
+
+        let summary: OverlapSummary = classify_lines(include_str!("../input.txt"))
+            .into_iter()
+            .map(|(_, o)| o)
+            .collect();
+        assert_eq!(summary.full_overlaps(), 441);
+        assert_eq!(summary.overlaps(), 861);
+    }
 }
@@ -2,6 +2,9 @@ use anyhow::{format_err, Error};
 use clap::Parser;
 use itertools::Itertools;
 use smallvec::SmallVec;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
 use std::fs;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -9,18 +12,74 @@ use std::str::FromStr;
 #[derive(Parser)]
 struct Input {
     input: PathBuf,
+    /// print the stacks after every move
+    #[arg(long)]
+    trace: bool,
+    /// run only this crane model: 9000, 9001, capacity:N or bottom
+    #[arg(long)]
+    crane: Option<String>,
+    /// after running the crane, rewind to this step and print the stacks
+    #[arg(long)]
+    seek: Option<usize>,
+    /// find moves from the input drawing to the drawing in this file
+    #[arg(long)]
+    target: Option<PathBuf>,
+    /// find moves giving these stack tops, `_` marking an empty stack
+    #[arg(long)]
+    target_top: Option<String>,
+    /// give up planning after exploring this many arrangements
+    #[arg(long, default_value_t = 200_000)]
+    max_states: usize,
 }
 
 fn main() -> Result<(), Error> {
     let opts = Input::parse();
 
-    let buf = fs::read_to_string(opts.input)?;
+    let buf = fs::read_to_string(&opts.input)?;
+    if opts.target.is_some() || opts.target_top.is_some() {
+        let crane = crane_model(opts.crane.as_deref().unwrap_or("9000"))?;
+        let target = match (&opts.target, &opts.target_top) {
+            (Some(p), _) => {
+                Target::Drawing(PlaneProblem::from_buf(&fs::read_to_string(p)?)?.stacks)
+            }
+            (None, Some(top)) => Target::Top(top.chars().collect()),
+            (None, None) => unreachable!(),
+        };
+        let plane_problem = PlaneProblem::from_buf(&buf)?;
+        let moves = plan_moves(
+            &plane_problem.stacks,
+            &target,
+            crane.as_ref(),
+            opts.max_states,
+        )?;
+        println!("{}\n", plane_problem.stacks);
+        for inst in moves {
+            println!("{inst}");
+        }
+        return Ok(());
+    }
+
+    if opts.crane.is_some() || opts.seek.is_some() {
+        let crane = crane_model(opts.crane.as_deref().unwrap_or("9000"))?;
+        let mut plane_problem = PlaneProblem::from_buf(&buf)?;
+        plane_problem.trace = opts.trace;
+        plane_problem.solve_with(crane)?;
+        println!("plane {}", plane_problem.stacks.get_stack_top());
+        if let Some(step) = opts.seek {
+            plane_problem.seek(step)?;
+            println!("step {step}\n{}", plane_problem.stacks);
+        }
+        return Ok(());
+    }
+
     let mut plane_problem = PlaneProblem::from_buf(&buf)?;
+    plane_problem.trace = opts.trace;
     plane_problem.solve()?;
     let stack_top = plane_problem.stacks.get_stack_top();
     println!("plane {stack_top}");
     assert_eq!(&stack_top, "TBVFVDZPN");
     let mut plane_problem = PlaneProblem::from_buf(&buf)?;
+    plane_problem.trace = opts.trace;
     plane_problem.solve_9001()?;
     let stack_top = plane_problem.stacks.get_stack_top();
     println!("plane {stack_top}");
@@ -31,6 +90,16 @@ fn main() -> Result<(), Error> {
 struct PlaneProblem {
     stacks: Stacks,
     instructions: Vec<MoveInstruction>,
+    trace: bool,
+    history: Vec<MoveRecord>,
+    crane: Box<dyn CraneModel>,
+}
+
+#[derive(Debug, Clone)]
+struct MoveRecord {
+    inst: MoveInstruction,
+    crates: Vec<char>,
+    from_bottom: bool,
 }
 
 impl PlaneProblem {
@@ -39,9 +108,9 @@ impl PlaneProblem {
         let mut instructions = Vec::new();
         for line in buf.split('\n') {
             if line.starts_with("move") {
-                let instruction: MoveInstruction = line.parse()?;
+                let instruction: MoveInstruction = line.trim_end().parse()?;
                 instructions.push(instruction);
-            } else if !line.is_empty() {
+            } else if !line.trim().is_empty() {
                 plane_buf.push(line);
             }
         }
@@ -49,25 +118,184 @@ impl PlaneProblem {
         Ok(Self {
             stacks,
             instructions,
+            trace: false,
+            history: Vec::new(),
+            crane: Box::new(CrateMover9000),
         })
     }
 
-    fn solve(&mut self) -> Result<(), Error> {
-        for i in &self.instructions {
-            self.stacks.process_move(*i)?;
+    fn step(&self) -> usize {
+        self.history.len()
+    }
+
+    fn set_crane(&mut self, crane: Box<dyn CraneModel>) -> Result<(), Error> {
+        if self.step() > 0 {
+            return Err(format_err!(
+                "Cannot change crane after {} recorded steps",
+                self.step()
+            ));
         }
+        self.crane = crane;
         Ok(())
     }
 
+    fn apply(&mut self, inst: MoveInstruction) -> Result<(), Error> {
+        self.stacks.stack(inst.to_stack)?;
+        let from_crates = self.stacks.stack(inst.from_stack)?;
+        if from_crates.len() < inst.ncrates {
+            return Err(format_err!("No crates to move"));
+        }
+        let from_bottom = self.crane.takes_from_bottom();
+        let crates = if from_bottom {
+            from_crates[..inst.ncrates].to_vec()
+        } else {
+            from_crates[from_crates.len() - inst.ncrates..].to_vec()
+        };
+        self.crane.process_move(&mut self.stacks, inst)?;
+        self.history.push(MoveRecord {
+            inst,
+            crates,
+            from_bottom,
+        });
+        self.trace_move(inst);
+        Ok(())
+    }
+
+    fn undo(&mut self) -> Result<bool, Error> {
+        match self.history.pop() {
+            Some(record) => {
+                self.stacks.restore(&record)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn redo(&mut self) -> Result<bool, Error> {
+        match self.instructions.get(self.step()) {
+            Some(inst) => {
+                self.apply(*inst)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn seek(&mut self, step: usize) -> Result<(), Error> {
+        if step > self.instructions.len() {
+            return Err(format_err!(
+                "Step {step} is past the last instruction {}",
+                self.instructions.len()
+            ));
+        }
+        while self.step() > step {
+            self.undo()?;
+        }
+        while self.step() < step {
+            self.redo()?;
+        }
+        Ok(())
+    }
+
+    fn trace_move(&self, inst: MoveInstruction) {
+        if self.trace {
+            println!("{inst}\n{}\n", self.stacks);
+        }
+    }
+
+    fn solve_with(&mut self, crane: Box<dyn CraneModel>) -> Result<(), Error> {
+        self.set_crane(crane)?;
+        while self.redo()? {}
+        Ok(())
+    }
+
+    fn solve(&mut self) -> Result<(), Error> {
+        self.solve_with(Box::new(CrateMover9000))
+    }
+
     fn solve_9001(&mut self) -> Result<(), Error> {
-        for i in &self.instructions {
-            self.stacks.process_move_9001(*i)?;
+        self.solve_with(Box::new(CrateMover9001))
+    }
+}
+
+trait CraneModel {
+    fn process_move(&self, stacks: &mut Stacks, inst: MoveInstruction) -> Result<(), Error>;
+
+    fn takes_from_bottom(&self) -> bool {
+        false
+    }
+}
+
+struct CrateMover9000;
+
+impl CraneModel for CrateMover9000 {
+    fn process_move(&self, stacks: &mut Stacks, inst: MoveInstruction) -> Result<(), Error> {
+        for _ in 0..inst.ncrates {
+            let crates = stacks.take_top(inst.from_stack, 1)?;
+            stacks.put(inst.to_stack, crates)?;
+        }
+        Ok(())
+    }
+}
+
+struct CrateMover9001;
+
+impl CraneModel for CrateMover9001 {
+    fn process_move(&self, stacks: &mut Stacks, inst: MoveInstruction) -> Result<(), Error> {
+        let crates = stacks.take_top(inst.from_stack, inst.ncrates)?;
+        stacks.put(inst.to_stack, crates)
+    }
+}
+
+struct CapacityCrane {
+    capacity: usize,
+}
+
+impl CraneModel for CapacityCrane {
+    fn process_move(&self, stacks: &mut Stacks, inst: MoveInstruction) -> Result<(), Error> {
+        let mut remaining = inst.ncrates;
+        while remaining > 0 {
+            let lift = remaining.min(self.capacity);
+            let crates = stacks.take_top(inst.from_stack, lift)?;
+            stacks.put(inst.to_stack, crates)?;
+            remaining -= lift;
         }
         Ok(())
     }
 }
 
-#[derive(Debug)]
+struct BottomCrane;
+
+impl CraneModel for BottomCrane {
+    fn process_move(&self, stacks: &mut Stacks, inst: MoveInstruction) -> Result<(), Error> {
+        let crates = stacks.take_bottom(inst.from_stack, inst.ncrates)?;
+        stacks.put(inst.to_stack, crates)
+    }
+
+    fn takes_from_bottom(&self) -> bool {
+        true
+    }
+}
+
+fn crane_model(name: &str) -> Result<Box<dyn CraneModel>, Error> {
+    match name {
+        "9000" => Ok(Box::new(CrateMover9000)),
+        "9001" => Ok(Box::new(CrateMover9001)),
+        "bottom" => Ok(Box::new(BottomCrane)),
+        _ => {
+            let capacity: usize = name
+                .strip_prefix("capacity:")
+                .ok_or_else(|| format_err!("Unknown crane {name}"))?
+                .parse()?;
+            if capacity == 0 {
+                return Err(format_err!("Crane capacity must be positive"));
+            }
+            Ok(Box::new(CapacityCrane { capacity }))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Stacks(Vec<Vec<char>>);
 
 impl Stacks {
@@ -76,62 +304,182 @@ impl Stacks {
     }
 
     fn from_str(s: &[&str]) -> Result<Self, Error> {
-        let rows = s.len();
-        let nstacks = (s[rows - 1].len() + 1) / 4;
-        let mut stacks = vec![Vec::new(); nstacks];
-        for row in s.iter().rev().skip(1) {
-            for (idx, mut chunk) in row.chars().chunks(4).into_iter().enumerate() {
-                if let Some(x) = chunk.nth(1) {
-                    if x == ' ' {
-                        continue;
+        let (footer, rows) = s.split_last().ok_or_else(|| format_err!("Empty drawing"))?;
+        let labels = parse_stack_labels(&expand_tabs(footer))?;
+        let mut stacks = vec![Vec::new(); labels.len()];
+        for (height, (index, row)) in rows.iter().enumerate().rev().enumerate() {
+            let line = index + 1;
+            let row = expand_tabs(row);
+            let mut pos = 0;
+            while pos < row.len() {
+                match row[pos] {
+                    c if c.is_whitespace() => pos += 1,
+                    '[' => {
+                        let x = match (row.get(pos + 1), row.get(pos + 2)) {
+                            (Some(x), Some(']')) if !x.is_whitespace() && *x != '[' => *x,
+                            _ => {
+                                return Err(format_err!(
+                                    "Unclosed crate at line {line} column {}",
+                                    pos + 1
+                                ))
+                            }
+                        };
+                        let idx = stack_at_column(&labels, pos + 1).ok_or_else(|| {
+                            format_err!(
+                                "Crate {x} at line {line} column {} is not above a stack label",
+                                pos + 2
+                            )
+                        })?;
+                        if stacks[idx].len() != height {
+                            return Err(format_err!(
+                                "Crate {x} at line {line} is not resting on stack {}",
+                                idx + 1
+                            ));
+                        }
+                        stacks[idx].push(x);
+                        pos += 3;
+                    }
+                    c => {
+                        return Err(format_err!(
+                            "Unexpected {c:?} at line {line} column {}",
+                            pos + 1
+                        ))
                     }
-                    let s = stacks
-                        .get_mut(idx)
-                        .ok_or_else(|| format_err!("Bad index"))?;
-                    s.push(x);
                 }
             }
         }
         Ok(Self(stacks))
     }
 
-    fn process_move(&mut self, inst: MoveInstruction) -> Result<(), Error> {
-        for _ in 0..inst.ncrates {
-            let cr = self
-                .0
-                .get_mut(inst.from_stack)
-                .and_then(|s| s.pop())
-                .ok_or_else(|| format_err!("No crates to move"))?;
-            let s = self
-                .0
-                .get_mut(inst.to_stack)
-                .ok_or_else(|| format_err!("Invalid Stack"))?;
-            s.push(cr);
+    fn stack(&self, stack: usize) -> Result<&Vec<char>, Error> {
+        self.0
+            .get(stack)
+            .ok_or_else(|| format_err!("Invalid Stack"))
+    }
+
+    fn restore(&mut self, record: &MoveRecord) -> Result<(), Error> {
+        self.take_top(record.inst.to_stack, record.crates.len())?;
+        let from_stack = self.stack_mut(record.inst.from_stack)?;
+        if record.from_bottom {
+            from_stack.splice(..0, record.crates.iter().copied());
+        } else {
+            from_stack.extend(&record.crates);
         }
         Ok(())
     }
 
-    fn process_move_9001(&mut self, inst: MoveInstruction) -> Result<(), Error> {
-        let mut crates_to_move = Vec::new();
-        for _ in 0..inst.ncrates {
-            let cr = self
-                .0
-                .get_mut(inst.from_stack)
-                .and_then(|s| s.pop())
-                .ok_or_else(|| format_err!("No crates to move"))?;
-            crates_to_move.push(cr);
+    fn stack_mut(&mut self, stack: usize) -> Result<&mut Vec<char>, Error> {
+        self.0
+            .get_mut(stack)
+            .ok_or_else(|| format_err!("Invalid Stack"))
+    }
+
+    fn take_top(&mut self, stack: usize, ncrates: usize) -> Result<Vec<char>, Error> {
+        let s = self.stack_mut(stack)?;
+        if s.len() < ncrates {
+            return Err(format_err!("No crates to move"));
         }
-        for cr in crates_to_move.into_iter().rev() {
-            let s = self
-                .0
-                .get_mut(inst.to_stack)
-                .ok_or_else(|| format_err!("Invalid Stack"))?;
-            s.push(cr);
+        Ok(s.split_off(s.len() - ncrates))
+    }
+
+    fn take_bottom(&mut self, stack: usize, ncrates: usize) -> Result<Vec<char>, Error> {
+        let s = self.stack_mut(stack)?;
+        if s.len() < ncrates {
+            return Err(format_err!("No crates to move"));
         }
+        Ok(s.drain(..ncrates).collect())
+    }
+
+    fn put(&mut self, stack: usize, crates: impl IntoIterator<Item = char>) -> Result<(), Error> {
+        self.stack_mut(stack)?.extend(crates);
         Ok(())
     }
 }
 
+fn expand_tabs(s: &str) -> Vec<char> {
+    let mut row = Vec::new();
+    for c in s.trim_end().chars() {
+        if c == '\t' {
+            row.push(' ');
+            while row.len() % 4 != 0 {
+                row.push(' ');
+            }
+        } else {
+            row.push(c);
+        }
+    }
+    row
+}
+
+fn parse_stack_labels(footer: &[char]) -> Result<Vec<(usize, usize)>, Error> {
+    let mut labels = Vec::new();
+    let mut pos = 0;
+    while pos < footer.len() {
+        if footer[pos].is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        while pos < footer.len() && !footer[pos].is_whitespace() {
+            pos += 1;
+        }
+        let label: String = footer[start..pos].iter().collect();
+        let n: usize = label
+            .parse()
+            .map_err(|_| format_err!("Bad stack label {label:?} in footer"))?;
+        if n != labels.len() + 1 {
+            return Err(format_err!(
+                "Expected stack label {}, found {n}",
+                labels.len() + 1
+            ));
+        }
+        labels.push((start, pos - 1));
+    }
+    if labels.is_empty() {
+        return Err(format_err!("Drawing has no stack labels"));
+    }
+    Ok(labels)
+}
+
+fn stack_at_column(labels: &[(usize, usize)], column: usize) -> Option<usize> {
+    if let Some(idx) = labels
+        .iter()
+        .position(|(start, end)| *start <= column && column <= *end)
+    {
+        return Some(idx);
+    }
+    let near: SmallVec<[usize; 2]> = labels
+        .iter()
+        .enumerate()
+        .filter(|(_, (start, end))| column + 1 == *start || column == end + 1)
+        .map(|(idx, _)| idx)
+        .collect();
+    match near.as_slice() {
+        [idx] => Some(*idx),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Stacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let height = self.0.iter().map(Vec::len).max().unwrap_or(0);
+        for row in (0..height).rev() {
+            let cells: SmallVec<[String; 9]> = self
+                .0
+                .iter()
+                .map(|s| match s.get(row) {
+                    Some(c) => format!("[{c}]"),
+                    None => "   ".into(),
+                })
+                .collect();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
+        let labels: SmallVec<[String; 9]> =
+            (1..=self.0.len()).map(|i| format!(" {i:<2}")).collect();
+        write!(f, "{}", labels.join(" "))
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct MoveInstruction {
     ncrates: usize,
@@ -161,6 +509,139 @@ impl FromStr for MoveInstruction {
     }
 }
 
+impl fmt::Display for MoveInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.ncrates,
+            self.from_stack + 1,
+            self.to_stack + 1
+        )
+    }
+}
+
+enum Target {
+    Drawing(Stacks),
+    Top(Vec<char>),
+}
+
+impl Target {
+    fn validate(&self, initial: &Stacks) -> Result<(), Error> {
+        match self {
+            Self::Drawing(target) => {
+                let sorted = |stacks: &Stacks| -> Vec<char> {
+                    stacks.0.iter().flatten().copied().sorted().collect()
+                };
+                if target.0.len() != initial.0.len() || sorted(target) != sorted(initial) {
+                    return Err(format_err!("Target does not hold the same crates"));
+                }
+            }
+            Self::Top(top) => {
+                if top.len() != initial.0.len() {
+                    return Err(format_err!(
+                        "Target top needs one entry for each of {} stacks",
+                        initial.0.len()
+                    ));
+                }
+                let mut available: HashMap<char, usize> = HashMap::new();
+                for c in initial.0.iter().flatten() {
+                    *available.entry(*c).or_default() += 1;
+                }
+                for c in top.iter().filter(|c| **c != '_') {
+                    match available.get_mut(c) {
+                        Some(n) if *n > 0 => *n -= 1,
+                        _ => {
+                            return Err(format_err!(
+                                "Target cannot be reached, not enough {c} crates"
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn distance(&self, stacks: &Stacks) -> usize {
+        match self {
+            Self::Drawing(target) => stacks
+                .0
+                .iter()
+                .zip(&target.0)
+                .filter(|(s, t)| s != t)
+                .count(),
+            Self::Top(top) => stacks
+                .0
+                .iter()
+                .zip(top)
+                .filter(|(s, t)| s.last().copied().unwrap_or('_') != **t)
+                .count(),
+        }
+    }
+}
+
+fn plan_moves(
+    initial: &Stacks,
+    target: &Target,
+    crane: &dyn CraneModel,
+    max_states: usize,
+) -> Result<Vec<MoveInstruction>, Error> {
+    target.validate(initial)?;
+
+    let mut states: Vec<(Stacks, Option<(usize, MoveInstruction)>)> = vec![(initial.clone(), None)];
+    let mut best: HashMap<Stacks, usize> = HashMap::new();
+    best.insert(initial.clone(), 0);
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((target.distance(initial), 0, 0)));
+
+    while let Some(Reverse((_, cost, index))) = queue.pop() {
+        let stacks = states[index].0.clone();
+        if best.get(&stacks).is_some_and(|c| *c < cost) {
+            continue;
+        }
+        if target.distance(&stacks) == 0 {
+            let mut moves = Vec::new();
+            let mut current = index;
+            while let Some((parent, inst)) = states[current].1 {
+                moves.push(inst);
+                current = parent;
+            }
+            moves.reverse();
+            return Ok(moves);
+        }
+        for from_stack in 0..stacks.0.len() {
+            for to_stack in (0..stacks.0.len()).filter(|t| *t != from_stack) {
+                for ncrates in 1..=stacks.0[from_stack].len() {
+                    let inst = MoveInstruction {
+                        ncrates,
+                        from_stack,
+                        to_stack,
+                    };
+                    let mut next = stacks.clone();
+                    if crane.process_move(&mut next, inst).is_err() {
+                        continue;
+                    }
+                    if best.get(&next).is_some_and(|c| *c <= cost + 1) {
+                        continue;
+                    }
+                    if states.len() >= max_states {
+                        return Err(format_err!("No plan found within {max_states} states"));
+                    }
+                    best.insert(next.clone(), cost + 1);
+                    queue.push(Reverse((
+                        cost + 1 + 2 * target.distance(&next),
+                        cost + 1,
+                        states.len(),
+                    )));
+                    states.push((next, Some((index, inst))));
+                }
+            }
+        }
+    }
+    Err(format_err!("Target cannot be reached"))
+}
+
 pub static TEST_BUF: &str = r#"    [D]    
 [N] [C]    
 [Z] [M] [P]
@@ -201,4 +682,233 @@ mod tests {
         assert_eq!(&plane_problem.stacks.get_stack_top(), "MCD");
         Ok(())
     }
+
+    #[test]
+    fn test_display() -> Result<(), Error> {
+        let plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        let drawing = plane_problem.stacks.to_string();
+        assert!(TEST_BUF.starts_with(&format!("{drawing}\n\n")));
+        let lines: Vec<&str> = drawing.split('\n').collect();
+        assert_eq!(Stacks::from_str(&lines)?.0, plane_problem.stacks.0);
+        assert_eq!(
+            plane_problem.instructions[0].to_string(),
+            "move 1 from 2 to 1"
+        );
+
+        let buf = include_str!("../input.txt");
+        let plane_problem = PlaneProblem::from_buf(buf)?;
+        assert!(buf.starts_with(&format!("{}\n", plane_problem.stacks)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_crane_models() -> Result<(), Error> {
+        let mut plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        plane_problem.solve_with(crane_model("capacity:1")?)?;
+        assert_eq!(&plane_problem.stacks.get_stack_top(), "CMZ");
+
+        let mut plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        plane_problem.solve_with(crane_model("capacity:3")?)?;
+        assert_eq!(&plane_problem.stacks.get_stack_top(), "MCD");
+
+        let mut plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        plane_problem.solve_with(crane_model("capacity:2")?)?;
+        assert_eq!(
+            plane_problem.stacks.0,
+            vec![vec!['M'], vec!['C'], vec!['P', 'N', 'D', 'Z']]
+        );
+
+        let mut plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        plane_problem.solve_with(Box::new(BottomCrane))?;
+        assert_eq!(
+            plane_problem.stacks.0,
+            vec![vec!['D'], vec!['C'], vec!['P', 'Z', 'N', 'M']]
+        );
+
+        let plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        let mut stacks = plane_problem.stacks.clone();
+        CrateMover9000.process_move(&mut stacks, "move 2 from 1 to 1".parse()?)?;
+        assert_eq!(stacks, plane_problem.stacks);
+        CrateMover9001.process_move(&mut stacks, "move 3 from 2 to 2".parse()?)?;
+        assert_eq!(stacks, plane_problem.stacks);
+
+        assert!(crane_model("capacity:0").is_err());
+        assert!(crane_model("9002").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_redo_seek() -> Result<(), Error> {
+        let mut plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        let initial = plane_problem.stacks.clone();
+        let mut states = vec![initial.clone()];
+        plane_problem.set_crane(Box::new(CrateMover9001))?;
+        while plane_problem.redo()? {
+            states.push(plane_problem.stacks.clone());
+        }
+        assert_eq!(plane_problem.step(), 4);
+        assert_eq!(&plane_problem.stacks.get_stack_top(), "MCD");
+
+        assert!(plane_problem.undo()?);
+        assert_eq!(plane_problem.stacks, states[3]);
+        plane_problem.seek(1)?;
+        assert_eq!(plane_problem.stacks, states[1]);
+        assert!(plane_problem.set_crane(Box::new(CrateMover9000)).is_err());
+        plane_problem.seek(3)?;
+        assert_eq!(plane_problem.stacks, states[3]);
+        plane_problem.seek(0)?;
+        assert_eq!(plane_problem.stacks, initial);
+        assert!(!plane_problem.undo()?);
+        assert!(plane_problem.seek(5).is_err());
+
+        plane_problem.solve_with(Box::new(BottomCrane))?;
+        plane_problem.seek(0)?;
+        assert_eq!(plane_problem.stacks, initial);
+        plane_problem.seek(4)?;
+        for (record, inst) in plane_problem
+            .history
+            .iter()
+            .zip(&plane_problem.instructions)
+        {
+            assert_eq!(record.crates.len(), inst.ncrates);
+        }
+
+        let mut plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        plane_problem.instructions = vec!["move 1 from 1 to 1".parse()?];
+        plane_problem.solve_with(Box::new(BottomCrane))?;
+        assert_eq!(plane_problem.stacks.0[0], ['N', 'Z']);
+        assert_eq!(plane_problem.history[0].crates, ['Z']);
+        assert!(plane_problem.undo()?);
+        assert_eq!(plane_problem.stacks, initial);
+
+        let mut plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        plane_problem
+            .instructions
+            .push("move 5 from 1 to 2".parse()?);
+        plane_problem
+            .solve_with(Box::new(CapacityCrane { capacity: 1 }))
+            .unwrap_err();
+        assert_eq!(plane_problem.step(), 4);
+        assert_eq!(&plane_problem.stacks.get_stack_top(), "CMZ");
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_moves() -> Result<(), Error> {
+        let plane_problem = PlaneProblem::from_buf(TEST_BUF)?;
+        let mut solved = PlaneProblem::from_buf(TEST_BUF)?;
+        solved.solve_9001()?;
+        let target = Target::Drawing(solved.stacks.clone());
+        let moves = plan_moves(&plane_problem.stacks, &target, &CrateMover9001, 100_000)?;
+        assert!(moves.len() <= 4);
+
+        let mut buf = format!("{}\n\n", plane_problem.stacks);
+        for inst in &moves {
+            buf.push_str(&format!("{inst}\n"));
+        }
+        let mut replayed = PlaneProblem::from_buf(&buf)?;
+        replayed.solve_9001()?;
+        assert_eq!(replayed.stacks, solved.stacks);
+
+        let target = Target::Top("CMZ".chars().collect());
+        let moves = plan_moves(&plane_problem.stacks, &target, &CrateMover9000, 100_000)?;
+        assert_eq!(moves.len(), 2);
+        let mut stacks = plane_problem.stacks.clone();
+        for inst in moves {
+            CrateMover9000.process_move(&mut stacks, inst)?;
+        }
+        assert_eq!(&stacks.get_stack_top(), "CMZ");
+
+        let target = Target::Top("__Z".chars().collect());
+        let moves = plan_moves(&plane_problem.stacks, &target, &CrateMover9000, 100_000)?;
+        assert_eq!(moves.len(), 2);
+
+        let target = Target::Top("NZD".chars().collect());
+        let err = plan_moves(&plane_problem.stacks, &target, &CrateMover9000, 10).unwrap_err();
+        assert!(err.to_string().contains("No plan found within"));
+        let moves = plan_moves(&plane_problem.stacks, &target, &CrateMover9000, 100_000)?;
+        assert!(moves.len() > 1);
+        let err = plan_moves(
+            &plane_problem.stacks,
+            &Target::Top("XYZ".chars().collect()),
+            &CrateMover9000,
+            1000,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Target cannot be reached"));
+        let err = plan_moves(
+            &plane_problem.stacks,
+            &Target::Top("ZZ_".chars().collect()),
+            &CrateMover9000,
+            1000,
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Target cannot be reached"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_moves_input() -> Result<(), Error> {
+        let plane_problem = PlaneProblem::from_buf(include_str!("../input.txt"))?;
+        let target = Target::Top("TBVFVDZPN".chars().collect());
+        let moves = plan_moves(&plane_problem.stacks, &target, &CrateMover9000, 200_000)?;
+        let mut stacks = plane_problem.stacks.clone();
+        for inst in moves {
+            CrateMover9000.process_move(&mut stacks, inst)?;
+        }
+        assert_eq!(&stacks.get_stack_top(), "TBVFVDZPN");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_drawing() -> Result<(), Error> {
+        let trimmed = "    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3";
+        let lines: Vec<&str> = trimmed.split('\n').collect();
+        let stacks = Stacks::from_str(&lines)?;
+        assert_eq!(stacks, PlaneProblem::from_buf(TEST_BUF)?.stacks);
+
+        let tabs = "\t[D]\n[N]\t[C]\n[Z] [M] [P]\n 1   2   3 ";
+        let lines: Vec<&str> = tabs.split('\n').collect();
+        assert_eq!(Stacks::from_str(&lines)?, stacks);
+
+        let wide = Stacks(
+            (0..12)
+                .map(|i| vec![(b'A' + i) as char; i as usize % 3])
+                .collect(),
+        );
+        let drawing = wide.to_string();
+        assert!(drawing.ends_with(" 9   10  11  12"));
+        let lines: Vec<&str> = drawing.split('\n').collect();
+        assert_eq!(Stacks::from_str(&lines)?, wide);
+
+        for (drawing, error) in [
+            ("", "Empty drawing"),
+            ("[A]\n   ", "Drawing has no stack labels"),
+            ("[A]\n 1   3", "Expected stack label 2, found 3"),
+            ("[A]\n a", "Bad stack label \"a\" in footer"),
+            ("[A\n 1", "Unclosed crate at line 1 column 1"),
+            ("[A]   x\n 1", "Unexpected 'x' at line 1 column 7"),
+            (
+                "        [A]\n 1   2",
+                "Crate A at line 1 column 10 is not above a stack label",
+            ),
+            (
+                "[A]\n\n 1   2",
+                "Crate A at line 1 is not resting on stack 1",
+            ),
+            (
+                "[A]\n    [B]\n 1   2",
+                "Crate A at line 1 is not resting on stack 1",
+            ),
+        ] {
+            let lines: Vec<&str> = drawing.split('\n').collect();
+            let lines = if drawing.is_empty() {
+                &[][..]
+            } else {
+                &lines[..]
+            };
+            assert_eq!(Stacks::from_str(lines).unwrap_err().to_string(), error);
+        }
+        Ok(())
+    }
 }
@@ -1,17 +1,93 @@
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use clap::Parser;
 use smallvec::SmallVec;
 use std::fs;
+use std::io::{self, BufReader, ErrorKind, Read};
 use std::path::PathBuf;
 
 #[derive(Parser)]
 struct Input {
+    /// datastream file, or `-` to read standard input with --stream
     input: PathBuf,
+    /// read the datastream in chunks instead of loading it into memory
+    #[arg(long)]
+    stream: bool,
+    /// marker lengths to scan for in a single pass, e.g. `4,14,20`
+    #[arg(long, value_delimiter = ',')]
+    lengths: Vec<usize>,
+    /// report every marker of each length rather than only the first
+    #[arg(long)]
+    all: bool,
+    /// report up to this many markers of each length
+    #[arg(long, conflicts_with = "all")]
+    first: Option<usize>,
+    /// split the datastream into messages and their packets
+    #[arg(long)]
+    frames: bool,
 }
 
 fn main() -> Result<(), Error> {
     let opts = Input::parse();
 
+    let open_input = || -> Result<Box<dyn Read>, Error> {
+        if opts.input.as_os_str() == "-" {
+            Ok(Box::new(io::stdin().lock()))
+        } else {
+            Ok(Box::new(fs::File::open(&opts.input)?))
+        }
+    };
+
+    if !opts.lengths.is_empty() {
+        let limit = if opts.all {
+            None
+        } else {
+            Some(opts.first.unwrap_or(1))
+        };
+        for scan in scan_markers(open_input()?, &opts.lengths, limit)? {
+            let offsets: SmallVec<[String; 4]> =
+                scan.offsets.iter().map(ToString::to_string).collect();
+            println!(
+                "length {}: {} markers [{}]",
+                scan.length,
+                scan.offsets.len(),
+                offsets.join(",")
+            );
+        }
+        return Ok(());
+    }
+
+    if opts.frames {
+        for message in FrameDecoder::new(open_input()?, 14) {
+            let message = message?;
+            println!(
+                "message at {} payload {} bytes",
+                message.offset,
+                message.payload.len()
+            );
+            for packet in message.packets() {
+                println!(
+                    "  packet at {} payload {} bytes",
+                    packet.offset,
+                    packet.payload.len()
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    if opts.stream {
+        let names = ["packet", "message"];
+        let mut found = [false; 2];
+        scan_markers_with(open_input()?, &[4, 14], Some(1), |i, index| {
+            found[i] = true;
+            println!("start {} {index}", names[i]);
+        })?;
+        for (name, _) in names.iter().zip(found).filter(|(_, found)| !found) {
+            println!("no start {name}");
+        }
+        return Ok(());
+    }
+
     let buf = fs::read(opts.input)?;
     let index = find_marker::<4>(&buf);
     println!("start packet {index} / {}", buf.len());
@@ -45,6 +121,188 @@ fn check_uniqueness<const L: usize>(v: &[u8]) -> bool {
     }
 }
 
+struct MarkerDetector {
+    window: Vec<u8>,
+    counts: [u32; 256],
+    duplicates: usize,
+    offset: usize,
+}
+
+impl MarkerDetector {
+    fn new(length: usize) -> Self {
+        Self {
+            window: vec![0; length],
+            counts: [0; 256],
+            duplicates: 0,
+            offset: 0,
+        }
+    }
+
+    fn length(&self) -> usize {
+        self.window.len()
+    }
+
+    fn push(&mut self, byte: u8) -> bool {
+        let slot = self.offset % self.length();
+        if self.offset >= self.length() {
+            let old = self.window[slot] as usize;
+            if self.counts[old] > 1 {
+                self.duplicates -= 1;
+            }
+            self.counts[old] -= 1;
+        }
+        self.window[slot] = byte;
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] > 1 {
+            self.duplicates += 1;
+        }
+        self.offset += 1;
+        self.offset >= self.length() && self.duplicates == 0
+    }
+
+    fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MarkerScan {
+    length: usize,
+    offsets: Vec<usize>,
+}
+
+fn scan_markers(
+    reader: impl Read,
+    lengths: &[usize],
+    limit: Option<usize>,
+) -> Result<Vec<MarkerScan>, Error> {
+    let mut scans: Vec<MarkerScan> = lengths
+        .iter()
+        .map(|length| MarkerScan {
+            length: *length,
+            offsets: Vec::new(),
+        })
+        .collect();
+    scan_markers_with(reader, lengths, limit, |i, offset| {
+        scans[i].offsets.push(offset)
+    })?;
+    Ok(scans)
+}
+
+fn scan_markers_with(
+    mut reader: impl Read,
+    lengths: &[usize],
+    limit: Option<usize>,
+    mut on_marker: impl FnMut(usize, usize),
+) -> Result<(), Error> {
+    if lengths.contains(&0) {
+        return Err(format_err!("Marker length must be positive"));
+    }
+    let mut detectors: Vec<MarkerDetector> =
+        lengths.iter().map(|l| MarkerDetector::new(*l)).collect();
+    let mut found = vec![0; lengths.len()];
+    let done = |found: &[usize]| limit.is_some_and(|n| found.iter().all(|f| *f >= n));
+    let mut chunk = vec![0; 64 * 1024];
+    while !done(&found) {
+        let n = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for byte in &chunk[..n] {
+            for (i, detector) in detectors.iter_mut().enumerate() {
+                if detector.push(*byte) && limit.is_none_or(|n| found[i] < n) {
+                    found[i] += 1;
+                    on_marker(i, detector.offset());
+                }
+            }
+            if done(&found) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+    offset: usize,
+    marker: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    fn packets(&self) -> impl Iterator<Item = Frame> + '_ {
+        let base = self.offset + self.marker.len();
+        FrameDecoder::new(&self.payload[..], 4)
+            .filter_map(Result::ok)
+            .map(move |packet| Frame {
+                offset: base + packet.offset,
+                ..packet
+            })
+    }
+}
+
+struct FrameDecoder<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+    detector: MarkerDetector,
+    position: usize,
+    current: Option<(usize, Vec<u8>)>,
+    pending: Vec<u8>,
+}
+
+impl<R: Read> FrameDecoder<R> {
+    fn new(reader: R, marker_length: usize) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            detector: MarkerDetector::new(marker_length.max(1)),
+            position: 0,
+            current: None,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for FrameDecoder<R> {
+    type Item = Result<Frame, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.bytes.next() {
+                None => {
+                    let (offset, marker) = self.current.take()?;
+                    let payload = std::mem::take(&mut self.pending);
+                    return Some(Ok(Frame {
+                        offset,
+                        marker,
+                        payload,
+                    }));
+                }
+                Some(Err(e)) => return Some(Err(e.into())),
+                Some(Ok(byte)) => {
+                    self.position += 1;
+                    self.pending.push(byte);
+                    if !self.detector.push(byte) {
+                        continue;
+                    }
+                    let length = self.detector.length();
+                    let marker = self.pending.split_off(self.pending.len() - length);
+                    self.detector = MarkerDetector::new(length);
+                    let payload = std::mem::take(&mut self.pending);
+                    let next = (self.position - length, marker);
+                    if let Some((offset, marker)) = self.current.replace(next) {
+                        return Some(Ok(Frame {
+                            offset,
+                            marker,
+                            payload,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,4 +326,150 @@ mod tests {
         assert_eq!(find_marker::<14>(b"zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw"), 26);
         Ok(())
     }
+
+    fn find_marker_in_reader(reader: impl Read, length: usize) -> Result<Option<usize>, Error> {
+        let scans = scan_markers(reader, &[length], Some(1))?;
+        Ok(scans[0].offsets.first().copied())
+    }
+
+    #[test]
+    fn test_find_marker_in_reader() -> Result<(), Error> {
+        for buf in [
+            &b"mjqjpqmgbljsphdztnvjfqwrcgsmlb"[..],
+            b"bvwbjplbgvbhsrlpgdmjqwftvncz",
+            b"nppdvjthqldpwncqszvftbrmjlhg",
+            b"nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg",
+            b"zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw",
+        ] {
+            assert_eq!(find_marker_in_reader(buf, 4)?, Some(find_marker::<4>(buf)));
+            assert_eq!(
+                find_marker_in_reader(buf, 14)?,
+                Some(find_marker::<14>(buf))
+            );
+        }
+        assert_eq!(find_marker_in_reader(&b"aabbaabb"[..], 3)?, None);
+        assert_eq!(find_marker_in_reader(&b"a"[..], 1)?, Some(1));
+        assert!(find_marker_in_reader(&b"abc"[..], 0).is_err());
+
+        let buf = include_bytes!("../input.txt");
+        assert_eq!(find_marker_in_reader(&buf[..], 4)?, Some(1707));
+        assert_eq!(find_marker_in_reader(&buf[..], 14)?, Some(3697));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_markers() -> Result<(), Error> {
+        let buf = &b"mjqjpqmgbljsphdztnvjfqwrcgsmlb"[..];
+        let scans = scan_markers(buf, &[4, 14], Some(1))?;
+        assert_eq!(
+            scans,
+            vec![
+                MarkerScan {
+                    length: 4,
+                    offsets: vec![7]
+                },
+                MarkerScan {
+                    length: 14,
+                    offsets: vec![19]
+                }
+            ]
+        );
+        let scans = scan_markers(buf, &[4], Some(3))?;
+        assert_eq!(scans[0].offsets, [7, 8, 9]);
+
+        let scans = scan_markers(&b"abcabcaab"[..], &[3, 2], None)?;
+        assert_eq!(scans[0].offsets, [3, 4, 5, 6, 7]);
+        assert_eq!(scans[1].offsets, [2, 3, 4, 5, 6, 7, 9]);
+        assert!(scan_markers(buf, &[0], None).is_err());
+
+        let buf = include_bytes!("../input.txt");
+        let scans = scan_markers(&buf[..], &[4, 14, 20], None)?;
+        assert_eq!(scans[0].offsets[0], 1707);
+        assert_eq!(scans[1].offsets[0], 3697);
+        for scan in scans {
+            for offset in scan.offsets {
+                let mut window: Vec<u8> = buf[offset - scan.length..offset].to_vec();
+                window.sort_unstable();
+                window.dedup();
+                assert_eq!(window.len(), scan.length);
+            }
+        }
+        Ok(())
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read past the last marker"))
+        }
+    }
+
+    #[test]
+    fn test_scan_markers_with() -> Result<(), Error> {
+        let buf = &b"mjqjpqmgbljsphdztnvjfqwrcgsmlb"[..];
+        let mut markers = Vec::new();
+        scan_markers_with(buf.chain(FailingReader), &[14, 4], Some(1), |i, offset| {
+            markers.push((i, offset))
+        })?;
+        assert_eq!(markers, [(1, 7), (0, 19)]);
+
+        let mut markers = Vec::new();
+        let result = scan_markers_with(buf.chain(FailingReader), &[4], None, |i, offset| {
+            markers.push((i, offset))
+        });
+        assert!(result.is_err());
+        let scans = scan_markers(buf, &[4], None)?;
+        assert_eq!(
+            markers
+                .iter()
+                .map(|(_, offset)| *offset)
+                .collect::<Vec<_>>(),
+            scans[0].offsets
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_decoder() -> Result<(), Error> {
+        let frames: Vec<Frame> =
+            FrameDecoder::new(&b"aaabcaaxyzzqq"[..], 3).collect::<Result<_, _>>()?;
+        assert_eq!(
+            frames,
+            vec![
+                Frame {
+                    offset: 2,
+                    marker: b"abc".to_vec(),
+                    payload: b"a".to_vec()
+                },
+                Frame {
+                    offset: 6,
+                    marker: b"axy".to_vec(),
+                    payload: b"zzqq".to_vec()
+                },
+            ]
+        );
+        assert_eq!(FrameDecoder::new(&b"aaaa"[..], 2).count(), 0);
+
+        let message = Frame {
+            offset: 10,
+            marker: b"abcd".to_vec(),
+            payload: b"xxwxyzzwxyz".to_vec(),
+        };
+        let packets: Vec<(usize, usize)> = message
+            .packets()
+            .map(|p| (p.offset, p.payload.len()))
+            .collect();
+        assert_eq!(packets, [(16, 0), (20, 1)]);
+
+        let buf = include_bytes!("../input.txt");
+        let messages: Vec<Frame> = FrameDecoder::new(&buf[..], 14).collect::<Result<_, _>>()?;
+        assert_eq!(messages[0].offset + 14, 3697);
+        let total: usize = messages
+            .iter()
+            .map(|m| m.marker.len() + m.payload.len())
+            .sum();
+        assert_eq!(messages[0].offset + total, buf.len());
+        Ok(())
+    }
 }
@@ -1,21 +1,107 @@
 use anyhow::{format_err, Error};
-use clap::{Parser};
+use clap::{Parser, ValueEnum};
+use maplit::btreemap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use smallvec::SmallVec;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
-use std::fmt::Write;
+use std::fmt::{self, Write};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Parser)]
 struct Input {
     input: PathBuf,
+    /// treat the input as a directory and measure it instead of a transcript
+    #[arg(long)]
+    walk: bool,
+    /// evaluate a query such as `dirs where size <= 100000 | sum`
+    #[arg(short, long)]
+    query: Option<String>,
+    /// treat the input as a JSON snapshot written by --save
+    #[arg(long)]
+    load: bool,
+    /// write a JSON snapshot of the tree
+    #[arg(long)]
+    save: Option<PathBuf>,
+    /// compare the tree against an earlier JSON snapshot
+    #[arg(long)]
+    diff: Option<PathBuf>,
+    /// print the tree in the given format
+    #[arg(short, long, value_enum)]
+    output: Option<OutputFormat>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Debug,
+    Json,
+    Ncdu,
+    Tree,
 }
 
 fn main() -> Result<(), Error> {
     let opts = Input::parse();
 
-    let buf = fs::read_to_string(&opts.input)?;
-    let node_buffer = process_buf(&buf)?;
+    let buf;
+    let node_buffer = if opts.walk {
+        let (node_buffer, errors) = NodeBuffer::from_path(&opts.input)?;
+        for e in &errors {
+            eprintln!("warning: {e}");
+        }
+        node_buffer
+    } else if opts.load {
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(&opts.input)?)?;
+        NodeBuffer::from_snapshot(&snapshot)?
+    } else {
+        buf = fs::read_to_string(&opts.input)?;
+        process_buf(&buf)?
+    };
+
+    if let Some(path) = &opts.save {
+        fs::write(path, serde_json::to_string(&node_buffer.snapshot(0))?)?;
+    }
+    if let Some(path) = &opts.diff {
+        let before: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)?;
+        print!("{}", before.diff(&node_buffer.snapshot(0)));
+        return Ok(());
+    }
+
+    if let Some(format) = opts.output {
+        match format {
+            OutputFormat::Debug => print!("{}", node_buffer.debug_output(0, 0)),
+            OutputFormat::Json => println!("{}", node_buffer.json_tree(0)),
+            OutputFormat::Ncdu => {
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                println!("{}", node_buffer.ncdu_export(timestamp));
+            }
+            OutputFormat::Tree => print!("{}", node_buffer.tree_view(0, 0)),
+        }
+        return Ok(());
+    }
+
+    if let Some(query) = &opts.query {
+        let variables = default_variables(node_buffer.total_size(0));
+        print!("{}", node_buffer.query(query, &variables)?);
+        return Ok(());
+    }
+
+    if opts.walk || opts.load {
+        print!("{}", node_buffer.debug_output(0, 0));
+        let total_size_root = node_buffer.total_size(0);
+        println!("total size {total_size_root}");
+        println!("seq10000 {}", node_buffer.sum_dir_leq_100000(0));
+        if let Some(threshold) = (30_000_000 + total_size_root).checked_sub(70_000_000) {
+            if let Some((_, size)) = node_buffer.smallest_directory_geq(0, threshold) {
+                println!("size {size}");
+            }
+        }
+        return Ok(());
+    }
+
     let seq10000 = node_buffer.sum_dir_leq_100000(0);
     println!("seq10000 {seq10000}");
     assert_eq!(seq10000, 1367870);
@@ -25,36 +111,63 @@ fn main() -> Result<(), Error> {
     let (_, size) = node_buffer.smallest_directory_geq(0, threshold).unwrap();
     println!("size {size}");
     assert_eq!(size, 549173);
-
-    let _ = node_buffer.debug_output(0, 0);
     Ok(())
 }
 
-fn process_buf(buf: &str) -> Result<NodeBuffer, Error> {
+fn default_variables(total_size: usize) -> BTreeMap<&'static str, usize> {
+    let disk_size = 70_000_000;
+    let needed = 30_000_000;
+    let free_needed = (needed + total_size).saturating_sub(disk_size);
+    btreemap! {
+        "total" => total_size,
+        "disk_size" => disk_size,
+        "needed" => needed,
+        "free_needed" => free_needed,
+    }
+}
+
+fn process_buf(buf: &str) -> Result<NodeBuffer<'_>, Error> {
     let mut node_buffer = NodeBuffer::new();
+    let mut listing_from = None;
     for line in buf.split('\n') {
         if line.is_empty() {
             continue;
         }
-        match CommandLine::from_str(line)? {
-            CommandLine::Cd("/") => {
-                node_buffer.current_directory = 0;
-            }
-            CommandLine::Cd("..") => {
-                let current_directory = node_buffer
-                    .get_current_directory()
-                    .ok_or_else(|| format_err!("Cannot find current_directory"))?;
-                let parent_directory = current_directory
-                    .get_parent_directory()
-                    .ok_or_else(|| format_err!("No parent directory"))?;
-                node_buffer.current_directory = parent_directory;
+        let command_line = CommandLine::from_str(line)?;
+        if !matches!(command_line, CommandLine::Dir(_) | CommandLine::File { .. }) {
+            if let Some(current_directory) = listing_from.take() {
+                node_buffer.current_directory = current_directory;
             }
+        }
+        match command_line {
             CommandLine::Cd(d) => {
                 node_buffer
                     .change_current_directory(d)
                     .ok_or_else(|| format_err!("Directory does not exist {d} {line}"))?;
             }
-            CommandLine::Ls => {}
+            CommandLine::Ls(None) => {}
+            CommandLine::Ls(Some(path)) => {
+                let index = node_buffer
+                    .resolve_path(path)
+                    .ok_or_else(|| format_err!("Directory does not exist {path} {line}"))?;
+                listing_from = Some(node_buffer.current_directory);
+                node_buffer.current_directory = index;
+            }
+            CommandLine::Mkdir(d) => {
+                node_buffer
+                    .make_directory(d)
+                    .ok_or_else(|| format_err!("Cannot create directory {d} {line}"))?;
+            }
+            CommandLine::Rm(path) => {
+                node_buffer
+                    .remove(path)
+                    .ok_or_else(|| format_err!("Cannot remove {path} {line}"))?;
+            }
+            CommandLine::Mv { from, to } => {
+                node_buffer
+                    .move_node(from, to)
+                    .ok_or_else(|| format_err!("Cannot move {from} to {to} {line}"))?;
+            }
             CommandLine::Dir(d) => {
                 node_buffer
                     .insert_directory(d)
@@ -72,23 +185,39 @@ fn process_buf(buf: &str) -> Result<NodeBuffer, Error> {
 
 enum CommandLine<'a> {
     Cd(&'a str),
-    Ls,
+    Ls(Option<&'a str>),
+    Mkdir(&'a str),
+    Rm(&'a str),
+    Mv { from: &'a str, to: &'a str },
     Dir(&'a str),
     File { name: &'a str, size: usize },
 }
 
 impl<'a> CommandLine<'a> {
     fn from_str(s: &'a str) -> Result<Self, Error> {
-        let v: SmallVec<[&str; 3]> = s.split(' ').take(3).collect();
-        if v[0] == "$" {
-            if v[1] == "cd" && v.len() == 3 {
-                Ok(Self::Cd(v[2]))
-            } else if v[1] == "ls" {
-                Ok(Self::Ls)
-            } else {
-                Err(format_err!("Command does not parse"))
+        let v: SmallVec<[&str; 4]> = s.split_whitespace().collect();
+        if v.first() == Some(&"$") {
+            let command = v.get(1).copied();
+            let allowed_flags: &[&str] = match command {
+                Some("mkdir") => &["-p"],
+                Some("rm") => &["-r", "-R", "-f", "-rf", "-fr"],
+                _ => &[],
+            };
+            let (flags, args): (SmallVec<[&str; 2]>, SmallVec<[&str; 2]>) =
+                v.iter().skip(2).copied().partition(|a| a.starts_with('-'));
+            if let Some(flag) = flags.iter().find(|f| !allowed_flags.contains(f)) {
+                return Err(format_err!("Unknown flag {flag} {s}"));
+            }
+            match (command, args.as_slice()) {
+                (Some("cd"), [d]) => Ok(Self::Cd(d)),
+                (Some("ls"), []) => Ok(Self::Ls(None)),
+                (Some("ls"), [path]) => Ok(Self::Ls(Some(path))),
+                (Some("mkdir"), [d]) => Ok(Self::Mkdir(d)),
+                (Some("rm"), [path]) => Ok(Self::Rm(path)),
+                (Some("mv"), [from, to]) => Ok(Self::Mv { from, to }),
+                _ => Err(format_err!("Command does not parse {s}")),
             }
-        } else if v[0] == "dir" {
+        } else if v.len() == 2 && v[0] == "dir" {
             Ok(Self::Dir(v[1]))
         } else if v.len() == 2 {
             let size = v[0].parse()?;
@@ -102,14 +231,15 @@ impl<'a> CommandLine<'a> {
 #[derive(Debug)]
 enum Inode<'a> {
     Directory {
-        name: &'a str,
+        name: Cow<'a, str>,
         parent_directory: Option<usize>,
-        child_directories: BTreeMap<&'a str, usize>,
-        child_files: BTreeMap<&'a str, usize>,
+        child_directories: BTreeMap<Cow<'a, str>, usize>,
+        child_files: BTreeMap<Cow<'a, str>, usize>,
+        total_size: usize,
     },
     File {
         parent_directory: usize,
-        name: &'a str,
+        name: Cow<'a, str>,
         size: usize,
     },
 }
@@ -117,10 +247,11 @@ enum Inode<'a> {
 impl Default for Inode<'_> {
     fn default() -> Self {
         Self::Directory {
-            name: "/",
+            name: "/".into(),
             parent_directory: None,
             child_directories: BTreeMap::new(),
             child_files: BTreeMap::new(),
+            total_size: 0,
         }
     }
 }
@@ -137,16 +268,30 @@ impl<'a> Inode<'a> {
         }
     }
 
-    fn new_directory(name: &'a str, parent_directory: Option<usize>) -> Self {
+    fn new_directory(name: Cow<'a, str>, parent_directory: Option<usize>) -> Self {
         Self::Directory {
             name,
             parent_directory,
             child_directories: BTreeMap::new(),
             child_files: BTreeMap::new(),
+            total_size: 0,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Directory { name, .. } | Self::File { name, .. } => name,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::Directory { total_size, .. } => *total_size,
+            Self::File { size, .. } => *size,
         }
     }
 
-    fn get_directory(&self, name: &'a str) -> Option<usize> {
+    fn get_directory(&self, name: &str) -> Option<usize> {
         if let Inode::Directory {
             child_directories, ..
         } = self
@@ -157,7 +302,55 @@ impl<'a> Inode<'a> {
         }
     }
 
-    fn insert_child_file(&mut self, child_name: &'a str, child_index: usize) -> Option<()> {
+    fn get_file(&self, name: &str) -> Option<usize> {
+        if let Inode::Directory { child_files, .. } = self {
+            child_files.get(name).copied()
+        } else {
+            None
+        }
+    }
+
+    fn get_child(&self, name: &str) -> Option<usize> {
+        self.get_directory(name).or_else(|| self.get_file(name))
+    }
+
+    fn remove_child(&mut self, name: &str) -> Option<usize> {
+        if let Inode::Directory {
+            child_directories,
+            child_files,
+            ..
+        } = self
+        {
+            child_directories
+                .remove(name)
+                .or_else(|| child_files.remove(name))
+        } else {
+            None
+        }
+    }
+
+    fn set_location(&mut self, new_parent: usize, new_name: Cow<'a, str>) {
+        match self {
+            Self::Directory {
+                name,
+                parent_directory,
+                ..
+            } => {
+                *name = new_name;
+                *parent_directory = Some(new_parent);
+            }
+            Self::File {
+                name,
+                parent_directory,
+                ..
+            } => {
+                *name = new_name;
+                *parent_directory = new_parent;
+            }
+        }
+    }
+
+    fn insert_child_file(&mut self, child_name: Cow<'a, str>, child_index: usize) -> Option<()> {
         if let Inode::Directory { child_files, .. } = self {
             child_files.insert(child_name, child_index);
             Some(())
@@ -166,7 +359,11 @@ impl<'a> Inode<'a> {
         }
     }
 
-    fn insert_child_directory(&mut self, child_name: &'a str, child_index: usize) -> Option<()> {
+    fn insert_child_directory(
+        &mut self,
+        child_name: Cow<'a, str>,
+        child_index: usize,
+    ) -> Option<()> {
         if let Inode::Directory {
             child_directories, ..
         } = self
@@ -204,6 +401,42 @@ struct NodeBuffer<'a> {
     current_directory: usize,
 }
 
+impl NodeBuffer<'static> {
+    fn from_path(root: &Path) -> Result<(Self, Vec<Error>), Error> {
+        if !fs::metadata(root)?.is_dir() {
+            return Err(format_err!("{} is not a directory", root.display()));
+        }
+        let mut node_buffer = Self::new();
+        let mut errors = Vec::new();
+        node_buffer.walk_directory(root, 0, &mut errors);
+        node_buffer.current_directory = 0;
+        Ok((node_buffer, errors))
+    }
+
+    fn from_snapshot(snapshot: &Snapshot) -> Result<Self, Error> {
+        let mut node_buffer = Self::new();
+        node_buffer.insert_snapshot(snapshot)?;
+        node_buffer.current_directory = 0;
+        Ok(node_buffer)
+    }
+
+    fn insert_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        let parent = self.current_directory;
+        for (name, child) in &snapshot.directories {
+            self.insert_directory(name.clone())
+                .map(|index| self.current_directory = index)
+                .ok_or_else(|| format_err!("Failed to insert directory {name}"))?;
+            self.insert_snapshot(child)?;
+            self.current_directory = parent;
+        }
+        for (name, size) in &snapshot.files {
+            self.insert_file(name.clone(), *size)
+                .ok_or_else(|| format_err!("Failed to insert file {name}"))?;
+        }
+        Ok(())
+    }
+}
+
 impl<'a> NodeBuffer<'a> {
     fn new() -> Self {
         let mut node_buffer = NodeBuffer::default();
@@ -217,32 +450,414 @@ impl<'a> NodeBuffer<'a> {
         self.buffer.get_mut(current_directory)
     }
 
-    fn insert_file(&mut self, name: &'a str, size: usize) -> Option<()> {
+    fn insert_file(&mut self, name: impl Into<Cow<'a, str>>, size: usize) -> Option<()> {
+        let name = name.into();
+        let current_directory = self.get_current_directory()?;
+        if current_directory.get_directory(&name).is_some() {
+            return None;
+        }
+        if let Some(index) = current_directory.get_file(&name) {
+            let previous_size = match self.buffer.get_mut(index) {
+                Some(Inode::File { size: s, .. }) => std::mem::replace(s, size),
+                _ => return None,
+            };
+            self.propagate_size(Some(self.current_directory), size, previous_size);
+            return Some(());
+        }
         let file_index = self.buffer.len();
         self.buffer.push(Inode::File {
             parent_directory: self.current_directory,
-            name,
+            name: name.clone(),
             size,
         });
         self.get_current_directory()?
-            .insert_child_file(name, file_index)
+            .insert_child_file(name, file_index)?;
+        self.propagate_size(Some(self.current_directory), size, 0);
+        Some(())
+    }
+
+    fn propagate_size(&mut self, mut index: Option<usize>, added: usize, removed: usize) {
+        while let Some(Inode::Directory {
+            parent_directory,
+            total_size,
+            ..
+        }) = index.and_then(|i| self.buffer.get_mut(i))
+        {
+            *total_size = *total_size + added - removed;
+            index = *parent_directory;
+        }
     }
 
-    fn insert_directory(&mut self, name: &'a str) -> Option<()> {
+    fn insert_directory(&mut self, name: impl Into<Cow<'a, str>>) -> Option<usize> {
+        let name = name.into();
+        let current_directory = self.get_current_directory()?;
+        if current_directory.get_file(&name).is_some() {
+            return None;
+        }
+        if let Some(index) = current_directory.get_directory(&name) {
+            return Some(index);
+        }
         let parent_directory = Some(self.current_directory);
         let index = self.buffer.len();
-        let directory_inode = Inode::new_directory(name, parent_directory);
+        let directory_inode = Inode::new_directory(name.clone(), parent_directory);
         self.buffer.push(directory_inode);
         self.get_current_directory()?
-            .insert_child_directory(name, index)
+            .insert_child_directory(name, index)?;
+        Some(index)
     }
 
-    fn change_current_directory(&mut self, name: &'a str) -> Option<()> {
-        let index = self.get_current_directory()?.get_directory(name)?;
-        self.current_directory = index;
+    fn resolve_path(&self, path: &str) -> Option<usize> {
+        let mut index = if path.starts_with('/') {
+            0
+        } else {
+            self.current_directory
+        };
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    index = self.buffer.get(index)?.get_parent_directory()?;
+                }
+                name => {
+                    index = self.buffer.get(index)?.get_directory(name)?;
+                }
+            }
+        }
+        Some(index)
+    }
+
+    fn resolve_parent<'p>(&self, path: &'p str) -> Option<(usize, &'p str)> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = match path.rsplit_once('/') {
+            Some(("", name)) => (0, name),
+            Some((parent, name)) => (self.resolve_path(parent)?, name),
+            None => (self.current_directory, path),
+        };
+        match name {
+            "" | "." | ".." => None,
+            name => Some((parent, name)),
+        }
+    }
+
+    fn change_current_directory(&mut self, path: &str) -> Option<()> {
+        self.current_directory = self.resolve_path(path)?;
         Some(())
     }
 
+    fn make_directory(&mut self, path: &'a str) -> Option<()> {
+        let current_directory = self.current_directory;
+        if path.starts_with('/') {
+            self.current_directory = 0;
+        }
+        let mut result = Some(());
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    result = self
+                        .get_current_directory()
+                        .and_then(|d| d.get_parent_directory())
+                        .map(|parent| self.current_directory = parent);
+                    if result.is_none() {
+                        break;
+                    }
+                }
+                name => {
+                    result = self
+                        .insert_directory(name)
+                        .and_then(|_| self.change_current_directory(name));
+                    if result.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        self.current_directory = current_directory;
+        result
+    }
+
+    fn remove(&mut self, path: &str) -> Option<()> {
+        let (parent, name) = self.resolve_parent(path)?;
+        let index = self.buffer.get_mut(parent)?.remove_child(name)?;
+        let size = self.total_size(index);
+        self.propagate_size(Some(parent), 0, size);
+        if self.is_ancestor(index, self.current_directory) {
+            self.current_directory = parent;
+        }
+        Some(())
+    }
+
+    fn move_node(&mut self, from: &str, to: &'a str) -> Option<()> {
+        let (source_parent, source_name) = self.resolve_parent(from)?;
+        let index = self.buffer.get(source_parent)?.get_child(source_name)?;
+        let current_name: Cow<'a, str> = self.buffer.get(index)?.name().to_string().into();
+        let (target_parent, target_name) = match self.resolve_path(to) {
+            Some(target) => (target, current_name),
+            None => {
+                let (target, name) = self.resolve_parent(to)?;
+                (target, name.into())
+            }
+        };
+        if self.is_ancestor(index, target_parent) {
+            return None;
+        }
+        let is_directory = matches!(self.buffer.get(index)?, Inode::Directory { .. });
+        let existing = self.buffer.get(target_parent)?.get_child(&target_name);
+        if let Some(existing) = existing {
+            if existing == index {
+                return Some(());
+            }
+            if is_directory || matches!(self.buffer.get(existing)?, Inode::Directory { .. }) {
+                return None;
+            }
+            self.buffer
+                .get_mut(target_parent)?
+                .remove_child(&target_name)?;
+            let size = self.total_size(existing);
+            self.propagate_size(Some(target_parent), 0, size);
+        }
+        self.buffer
+            .get_mut(source_parent)?
+            .remove_child(source_name)?;
+        let size = self.total_size(index);
+        self.propagate_size(Some(source_parent), 0, size);
+        self.propagate_size(Some(target_parent), size, 0);
+        let target = self.buffer.get_mut(target_parent)?;
+        if is_directory {
+            target.insert_child_directory(target_name.clone(), index)?;
+        } else {
+            target.insert_child_file(target_name.clone(), index)?;
+        }
+        self.buffer
+            .get_mut(index)?
+            .set_location(target_parent, target_name);
+        Some(())
+    }
+
+    fn is_ancestor(&self, ancestor: usize, mut index: usize) -> bool {
+        loop {
+            if index == ancestor {
+                return true;
+            }
+            match self.buffer.get(index).and_then(Inode::get_parent_directory) {
+                Some(parent) => index = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn walk_directory(&mut self, path: &Path, index: usize, errors: &mut Vec<Error>) {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push(format_err!("{}: {e}", path.display()));
+                return;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    errors.push(format_err!("{}: {e}", path.display()));
+                    continue;
+                }
+            };
+            let child_path = entry.path();
+            let metadata = match fs::symlink_metadata(&child_path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    errors.push(format_err!("{}: {e}", child_path.display()));
+                    continue;
+                }
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            self.current_directory = index;
+            if self.buffer[index].get_child(&name).is_some() {
+                errors.push(format_err!(
+                    "{}: name collides with an existing entry {name}",
+                    child_path.display()
+                ));
+                continue;
+            }
+            if metadata.is_dir() {
+                match self.insert_directory(name) {
+                    Some(child_index) => self.walk_directory(&child_path, child_index, errors),
+                    None => errors.push(format_err!(
+                        "{}: failed to insert directory",
+                        child_path.display()
+                    )),
+                }
+            } else if self.insert_file(name, metadata.len() as usize).is_none() {
+                errors.push(format_err!(
+                    "{}: failed to insert file",
+                    child_path.display()
+                ));
+            }
+        }
+    }
+
+    fn snapshot(&self, index: usize) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        if let Some(Inode::Directory {
+            child_directories,
+            child_files,
+            ..
+        }) = self.buffer.get(index)
+        {
+            for (name, d) in child_directories {
+                snapshot
+                    .directories
+                    .insert(name.to_string(), self.snapshot(*d));
+            }
+            for (name, f) in child_files {
+                snapshot.files.insert(name.to_string(), self.total_size(*f));
+            }
+        }
+        snapshot
+    }
+
+    fn children(&self, index: usize) -> Vec<usize> {
+        match self.buffer.get(index) {
+            Some(Inode::Directory {
+                child_directories,
+                child_files,
+                ..
+            }) => child_directories
+                .values()
+                .chain(child_files.values())
+                .copied()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn json_tree(&self, index: usize) -> Value {
+        let node = self.buffer.get(index).unwrap();
+        match node {
+            Inode::Directory { .. } => {
+                let children: Vec<Value> = self
+                    .children(index)
+                    .into_iter()
+                    .map(|i| self.json_tree(i))
+                    .collect();
+                json!({
+                    "name": node.name(),
+                    "type": "directory",
+                    "size": node.size(),
+                    "children": children,
+                })
+            }
+            Inode::File { .. } => json!({
+                "name": node.name(),
+                "type": "file",
+                "size": node.size(),
+            }),
+        }
+    }
+
+    fn ncdu_export(&self, timestamp: u64) -> Value {
+        json!([
+            1,
+            0,
+            {
+                "progname": env!("CARGO_PKG_NAME"),
+                "progver": env!("CARGO_PKG_VERSION"),
+                "timestamp": timestamp,
+            },
+            self.ncdu_entry(0),
+        ])
+    }
+
+    fn ncdu_entry(&self, index: usize) -> Value {
+        let node = self.buffer.get(index).unwrap();
+        match node {
+            Inode::Directory { .. } => {
+                let mut entries = vec![json!({ "name": node.name() })];
+                entries.extend(self.children(index).into_iter().map(|i| self.ncdu_entry(i)));
+                Value::Array(entries)
+            }
+            Inode::File { .. } => json!({
+                "name": node.name(),
+                "asize": node.size(),
+                "dsize": node.size(),
+            }),
+        }
+    }
+
+    fn tree_view(&self, index: usize, indent: usize) -> String {
+        let node = self.buffer.get(index).unwrap();
+        let mut output = String::new();
+        if indent == 0 {
+            writeln!(
+                &mut output,
+                "{:>10}        {}",
+                human_size(node.size()),
+                node.name()
+            )
+            .unwrap();
+        }
+        let mut children = self.children(index);
+        children.sort_by_key(|i| (std::cmp::Reverse(self.total_size(*i)), *i));
+        let indent_str: String = (0..=indent).map(|_| "  ").collect();
+        for child in children {
+            let child_node = self.buffer.get(child).unwrap();
+            let percentage = if node.size() == 0 {
+                0.0
+            } else {
+                100.0 * child_node.size() as f64 / node.size() as f64
+            };
+            let suffix = if let Inode::Directory { .. } = child_node {
+                "/"
+            } else {
+                ""
+            };
+            writeln!(
+                &mut output,
+                "{:>10} {percentage:5.1}% {indent_str}{}{suffix}",
+                human_size(child_node.size()),
+                child_node.name(),
+            )
+            .unwrap();
+            output.push_str(&self.tree_view(child, indent + 1));
+        }
+        output
+    }
+
+    fn query_rows(&self, index: usize, path: String, source: QuerySource) -> Vec<QueryRow> {
+        let mut rows = Vec::new();
+        if let Some(Inode::Directory {
+            child_directories,
+            child_files,
+            ..
+        }) = self.buffer.get(index)
+        {
+            let prefix = path.trim_end_matches('/');
+            if source == QuerySource::Dirs {
+                rows.push(QueryRow {
+                    size: self.total_size(index),
+                    path: path.clone(),
+                });
+            } else {
+                for (name, f) in child_files {
+                    if let Some(Inode::File { size, .. }) = self.buffer.get(*f) {
+                        rows.push(QueryRow {
+                            path: format!("{prefix}/{name}"),
+                            size: *size,
+                        });
+                    }
+                }
+            }
+            for (name, d) in child_directories {
+                rows.extend(self.query_rows(*d, format!("{prefix}/{name}"), source));
+            }
+        }
+        rows
+    }
+
+    fn query(&self, query: &str, variables: &BTreeMap<&str, usize>) -> Result<QueryResult, Error> {
+        query.parse::<Query>()?.evaluate(self, variables)
+    }
+
     fn debug_output(&self, index: usize, indent: usize) -> String {
         let current_node = self.buffer.get(index).unwrap();
         let mut output = String::new();
@@ -270,30 +885,13 @@ impl<'a> NodeBuffer<'a> {
     }
 
     fn total_size(&self, index: usize) -> usize {
-        let current_node = self.buffer.get(index).unwrap();
-        let mut total_size = 0;
-        if let Inode::Directory {
-            child_directories,
-            child_files,
-            ..
-        } = current_node
-        {
-            for d in child_directories.values() {
-                total_size += self.total_size(*d);
-            }
-            for f in child_files.values() {
-                if let Some(Inode::File { size, .. }) = self.buffer.get(*f) {
-                    total_size += *size;
-                }
-            }
-        }
-        total_size
+        self.buffer.get(index).unwrap().size()
     }
 
     fn sum_dir_leq_100000(&self, index: usize) -> usize {
         let current_node = self.buffer.get(index).unwrap();
         let mut total_size = 0;
-        let size = self.total_size(0);
+        let size = self.total_size(index);
         if size <= 100_000 {
             total_size += size;
         }
@@ -302,10 +900,6 @@ impl<'a> NodeBuffer<'a> {
         } = current_node
         {
             for d in child_directories.values() {
-                let size = self.total_size(*d);
-                if size <= 100_000 {
-                    total_size += size
-                }
                 total_size += self.sum_dir_leq_100000(*d);
             }
         }
@@ -340,6 +934,493 @@ impl<'a> NodeBuffer<'a> {
     }
 }
 
+fn human_size(size: usize) -> String {
+    let units = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < units.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", units[unit])
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+struct Snapshot {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    directories: BTreeMap<String, Snapshot>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    files: BTreeMap<String, usize>,
+}
+
+impl Snapshot {
+    fn flatten(
+        &self,
+        path: &str,
+        files: &mut BTreeMap<String, usize>,
+        directories: &mut BTreeMap<String, usize>,
+    ) -> usize {
+        let prefix = path.trim_end_matches('/');
+        let mut total_size = 0;
+        for (name, size) in &self.files {
+            files.insert(format!("{prefix}/{name}"), *size);
+            total_size += size;
+        }
+        for (name, child) in &self.directories {
+            total_size += child.flatten(&format!("{prefix}/{name}"), files, directories);
+        }
+        directories.insert(path.into(), total_size);
+        total_size
+    }
+
+    fn diff(&self, after: &Self) -> SnapshotDiff {
+        let (mut files_before, mut directories_before) = (BTreeMap::new(), BTreeMap::new());
+        let (mut files_after, mut directories_after) = (BTreeMap::new(), BTreeMap::new());
+        self.flatten("/", &mut files_before, &mut directories_before);
+        after.flatten("/", &mut files_after, &mut directories_after);
+
+        let mut diff = SnapshotDiff::default();
+        for (path, before) in &files_before {
+            match files_after.get(path) {
+                None => diff.files.push(FileChange::Removed {
+                    path: path.clone(),
+                    size: *before,
+                }),
+                Some(after) if after != before => diff.files.push(FileChange::Resized {
+                    path: path.clone(),
+                    before: *before,
+                    after: *after,
+                }),
+                Some(_) => {}
+            }
+        }
+        for (path, after) in &files_after {
+            if !files_before.contains_key(path) {
+                diff.files.push(FileChange::Added {
+                    path: path.clone(),
+                    size: *after,
+                });
+            }
+        }
+        diff.files.sort_by(|a, b| a.path().cmp(b.path()));
+
+        let mut paths: Vec<&String> = directories_before.keys().collect();
+        paths.extend(directories_after.keys());
+        paths.sort();
+        paths.dedup();
+        for path in paths {
+            let before = directories_before.get(path).copied().unwrap_or(0) as i64;
+            let after = directories_after.get(path).copied().unwrap_or(0) as i64;
+            if before != after {
+                diff.directory_deltas.push((path.clone(), after - before));
+            }
+        }
+        diff
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FileChange {
+    Added {
+        path: String,
+        size: usize,
+    },
+    Removed {
+        path: String,
+        size: usize,
+    },
+    Resized {
+        path: String,
+        before: usize,
+        after: usize,
+    },
+}
+
+impl FileChange {
+    fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Resized { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+impl fmt::Display for FileChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Added { path, size } => write!(f, "+ {path} ({size})"),
+            Self::Removed { path, size } => write!(f, "- {path} ({size})"),
+            Self::Resized {
+                path,
+                before,
+                after,
+            } => write!(f, "~ {path} ({before} -> {after})"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct SnapshotDiff {
+    files: Vec<FileChange>,
+    directory_deltas: Vec<(String, i64)>,
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.files {
+            writeln!(f, "{change}")?;
+        }
+        for (path, delta) in &self.directory_deltas {
+            writeln!(f, "dir {path} {delta:+}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QuerySource {
+    Dirs,
+    Files,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryField {
+    Name,
+    Path,
+    Size,
+}
+
+impl FromStr for QueryField {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "path" => Ok(Self::Path),
+            "size" => Ok(Self::Size),
+            _ => Err(format_err!("Unknown field {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Glob,
+}
+
+impl FromStr for QueryOp {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "<" => Ok(Self::Lt),
+            "<=" => Ok(Self::Le),
+            ">" => Ok(Self::Gt),
+            ">=" => Ok(Self::Ge),
+            "==" | "=" => Ok(Self::Eq),
+            "!=" => Ok(Self::Ne),
+            "~" => Ok(Self::Glob),
+            _ => Err(format_err!("Unknown operator {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum QueryValue {
+    Number(usize),
+    Text(String),
+    Variable(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct QueryCondition {
+    field: QueryField,
+    op: QueryOp,
+    value: QueryValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum QueryStage {
+    Sum,
+    Count,
+    Min(QueryField),
+    Max(QueryField),
+    Top(usize, QueryField),
+    Sort(QueryField),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Query {
+    source: QuerySource,
+    conditions: Vec<QueryCondition>,
+    stages: Vec<QueryStage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryToken<'a> {
+    Word(&'a str),
+    Quoted(&'a str),
+    Pipe,
+}
+
+fn tokenize_query(s: &str) -> Result<Vec<QueryToken<'_>>, Error> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('|') {
+            tokens.push(QueryToken::Pipe);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('"') {
+            let end = r
+                .find('"')
+                .ok_or_else(|| format_err!("Unterminated string in {s}"))?;
+            tokens.push(QueryToken::Quoted(&r[..end]));
+            rest = &r[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '|' || c == '"')
+                .unwrap_or(rest.len());
+            tokens.push(QueryToken::Word(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+impl FromStr for Query {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize_query(s)?;
+        let mut parts = tokens.split(|t| *t == QueryToken::Pipe);
+        let head = parts.next().unwrap_or_default();
+        let source = match head.first() {
+            Some(QueryToken::Word("dirs")) => QuerySource::Dirs,
+            Some(QueryToken::Word("files")) => QuerySource::Files,
+            _ => return Err(format_err!("Query must start with dirs or files")),
+        };
+        let mut conditions = Vec::new();
+        match &head[1..] {
+            [] => {}
+            [QueryToken::Word("where"), rest @ ..] => {
+                for condition in rest.split(|t| *t == QueryToken::Word("and")) {
+                    conditions.push(QueryCondition::parse(condition)?);
+                }
+            }
+            _ => return Err(format_err!("Expected where after source")),
+        }
+        let stages = parts.map(QueryStage::parse).collect::<Result<_, _>>()?;
+        Ok(Self {
+            source,
+            conditions,
+            stages,
+        })
+    }
+}
+
+impl QueryCondition {
+    fn parse(tokens: &[QueryToken]) -> Result<Self, Error> {
+        let [QueryToken::Word(field), QueryToken::Word(op), value] = tokens else {
+            return Err(format_err!("Condition must be <field> <op> <value>"));
+        };
+        let field: QueryField = field.parse()?;
+        let op: QueryOp = op.parse()?;
+        let value = match value {
+            QueryToken::Quoted(text) => QueryValue::Text((*text).into()),
+            QueryToken::Word(word) => match word.parse() {
+                Ok(number) => QueryValue::Number(number),
+                Err(_) => QueryValue::Variable((*word).into()),
+            },
+            QueryToken::Pipe => return Err(format_err!("Missing value")),
+        };
+        let valid = match (field, &value) {
+            (QueryField::Size, QueryValue::Text(_)) => false,
+            (QueryField::Size, _) => op != QueryOp::Glob,
+            (_, value) => matches!(value, QueryValue::Text(_)),
+        };
+        if !valid {
+            return Err(format_err!(
+                "Cannot compare {field:?} with {op:?} {value:?}"
+            ));
+        }
+        Ok(Self { field, op, value })
+    }
+
+    fn matches(&self, row: &QueryRow, variables: &BTreeMap<&str, usize>) -> Result<bool, Error> {
+        let ordering = match (&self.value, self.field) {
+            (QueryValue::Text(text), _) => {
+                let s = row.text(self.field);
+                match self.op {
+                    QueryOp::Glob => return Ok(glob_match(text, s)),
+                    _ => s.cmp(text.as_str()),
+                }
+            }
+            (QueryValue::Number(number), _) => row.size.cmp(number),
+            (QueryValue::Variable(variable), _) => {
+                let number = variables
+                    .get(variable.as_str())
+                    .ok_or_else(|| format_err!("Unknown variable {variable}"))?;
+                row.size.cmp(number)
+            }
+        };
+        Ok(match self.op {
+            QueryOp::Lt => ordering.is_lt(),
+            QueryOp::Le => ordering.is_le(),
+            QueryOp::Gt => ordering.is_gt(),
+            QueryOp::Ge => ordering.is_ge(),
+            QueryOp::Eq | QueryOp::Glob => ordering.is_eq(),
+            QueryOp::Ne => ordering.is_ne(),
+        })
+    }
+}
+
+impl QueryStage {
+    fn parse(tokens: &[QueryToken]) -> Result<Self, Error> {
+        use QueryToken::Word;
+        match tokens {
+            [Word("sum")] | [Word("sum"), Word("size")] => Ok(Self::Sum),
+            [Word("count")] => Ok(Self::Count),
+            [Word("min"), Word(field)] => Ok(Self::Min(field.parse()?)),
+            [Word("max"), Word(field)] => Ok(Self::Max(field.parse()?)),
+            [Word("top"), Word(n), Word("by"), Word(field)] => {
+                Ok(Self::Top(n.parse()?, field.parse()?))
+            }
+            [Word("sort"), Word("by"), Word(field)] => Ok(Self::Sort(field.parse()?)),
+            _ => Err(format_err!("Unknown stage {tokens:?}")),
+        }
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let mut matched = vec![false; text.len() + 1];
+    matched[0] = true;
+    for p in &pattern {
+        let mut next = vec![false; text.len() + 1];
+        if *p == '*' {
+            next[0] = matched[0];
+        }
+        for (i, c) in text.iter().enumerate() {
+            next[i + 1] = match p {
+                '*' => next[i] || matched[i + 1],
+                '?' => matched[i],
+                p => matched[i] && p == c,
+            };
+        }
+        matched = next;
+    }
+    matched[text.len()]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct QueryRow {
+    path: String,
+    size: usize,
+}
+
+impl QueryRow {
+    fn name(&self) -> &str {
+        match self.path.rsplit_once('/') {
+            Some((_, "")) | None => "/",
+            Some((_, name)) => name,
+        }
+    }
+
+    fn text(&self, field: QueryField) -> &str {
+        match field {
+            QueryField::Path => &self.path,
+            _ => self.name(),
+        }
+    }
+
+    fn compare(&self, other: &Self, field: QueryField) -> std::cmp::Ordering {
+        match field {
+            QueryField::Size => self.size.cmp(&other.size),
+            field => self.text(field).cmp(other.text(field)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum QueryResult {
+    Number(usize),
+    Rows(Vec<QueryRow>),
+}
+
+impl fmt::Display for QueryResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(n) => writeln!(f, "{n}"),
+            Self::Rows(rows) => {
+                for row in rows {
+                    writeln!(f, "{} {}", row.size, row.path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Query {
+    fn evaluate(
+        &self,
+        node_buffer: &NodeBuffer,
+        variables: &BTreeMap<&str, usize>,
+    ) -> Result<QueryResult, Error> {
+        let mut rows = Vec::new();
+        for row in node_buffer.query_rows(0, "/".into(), self.source) {
+            let mut keep = true;
+            for condition in &self.conditions {
+                keep &= condition.matches(&row, variables)?;
+            }
+            if keep {
+                rows.push(row);
+            }
+        }
+        let mut result = QueryResult::Rows(rows);
+        for stage in &self.stages {
+            let QueryResult::Rows(mut rows) = result else {
+                return Err(format_err!("Cannot apply {stage:?} to a number"));
+            };
+            result = match *stage {
+                QueryStage::Sum => QueryResult::Number(rows.iter().map(|r| r.size).sum()),
+                QueryStage::Count => QueryResult::Number(rows.len()),
+                QueryStage::Min(field) => QueryResult::Rows(
+                    rows.into_iter()
+                        .min_by(|a, b| a.compare(b, field))
+                        .into_iter()
+                        .collect(),
+                ),
+                QueryStage::Max(field) => QueryResult::Rows(
+                    rows.into_iter()
+                        .max_by(|a, b| a.compare(b, field))
+                        .into_iter()
+                        .collect(),
+                ),
+                QueryStage::Top(n, field) => {
+                    rows.sort_by(|a, b| b.compare(a, field));
+                    rows.truncate(n);
+                    QueryResult::Rows(rows)
+                }
+                QueryStage::Sort(field) => {
+                    rows.sort_by(|a, b| a.compare(b, field));
+                    QueryResult::Rows(rows)
+                }
+            };
+        }
+        Ok(result)
+    }
+}
+
 pub static TEST_BUF: &str = "
 $ cd /
 $ ls
@@ -400,4 +1481,252 @@ mod tests {
         assert_eq!(size, 24933642);
         Ok(())
     }
+
+    #[test]
+    fn test_sum_dir_leq_100000_small_root() -> Result<(), Error> {
+        let node_buffer = process_buf("$ cd /\n$ ls\ndir a\n10 f\n$ cd a\n$ ls\n20 g\n")?;
+        assert_eq!(node_buffer.total_size(0), 30);
+        assert_eq!(node_buffer.sum_dir_leq_100000(0), 30 + 20);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_path() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("day7_from_path_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/e"))?;
+        fs::create_dir_all(root.join("d"))?;
+        fs::write(root.join("a/e/i"), vec![0; 584])?;
+        fs::write(root.join("a/f"), vec![0; 2911])?;
+        fs::write(root.join("b.txt"), vec![0; 1000])?;
+        fs::write(root.join("d/j"), vec![0; 40])?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("a"), root.join("d/link"))?;
+
+        let (node_buffer, errors) = NodeBuffer::from_path(&root)?;
+        assert!(errors.is_empty());
+        let output = node_buffer.debug_output(0, 0);
+        assert!(output
+            .starts_with("- / (dir)\n  - a (dir)\n    - e (dir)\n      - i (file, size=584)\n"));
+        let link_size = fs::symlink_metadata(root.join("d/link")).map_or(0, |m| m.len() as usize);
+        assert_eq!(
+            node_buffer.total_size(0),
+            584 + 2911 + 1000 + 40 + link_size
+        );
+
+        assert!(NodeBuffer::from_path(&root.join("b.txt")).is_err());
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_path_name_clash() -> Result<(), Error> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = std::env::temp_dir().join(format!("day7_name_clash_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(OsStr::from_bytes(b"x\xff")))?;
+        fs::write(root.join(OsStr::from_bytes(b"x\xfe")), vec![0; 10])?;
+        fs::write(root.join(OsStr::from_bytes(b"x\xff/f")), vec![0; 5])?;
+
+        let (node_buffer, errors) = NodeBuffer::from_path(&root)?;
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("collides"));
+        assert!(node_buffer.buffer[0].get_child("x\u{fffd}").is_some());
+        assert!(matches!(node_buffer.total_size(0), 5 | 10));
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_richer_transcript() -> Result<(), Error> {
+        let buf = "
+$ cd /
+$ mkdir -p a/b/c
+$ cd a/b/c
+$ ls
+100 x.log
+dir d
+$ ls
+150 x.log
+dir d
+$ cd /a/b/../b/c/d
+$ ls
+20 y
+$ cd /
+$ ls
+dir a
+7 z
+$ mv a/b/c/x.log a/moved.log
+$ mv z a/b
+$ rm -r a/b/c/d
+$ cd a/b/../..
+$ cd a
+$ mkdir ../x/../y
+$ ls ../x
+3 w
+$ ls
+dir b
+";
+        let node_buffer = process_buf(buf)?;
+        let output = node_buffer.debug_output(0, 0);
+        assert_eq!(
+            &output,
+            "- / (dir)\n  - a (dir)\n    - b (dir)\n      - c (dir)\n      - z (file, size=7)\n    - moved.log (file, size=150)\n  - x (dir)\n    - w (file, size=3)\n  - y (dir)\n"
+        );
+        assert_eq!(node_buffer.total_size(0), 160);
+
+        assert!(process_buf("$ cd missing\n").is_err());
+        assert!(process_buf("$ mkdir a\n$ mv a a/b\n").is_err());
+        assert!(process_buf("$ ls\n1 f\n$ mkdir f/g\n").is_err());
+        assert!(process_buf("$ rm nothing\n").is_err());
+        assert!(process_buf("$ ls missing\n1 f\n").is_err());
+        assert!(process_buf("$ mkdir a\n$ mkdir b\n$ ls a b\n").is_err());
+        assert!(process_buf("$ cd ..\n").is_err());
+        assert!(process_buf("$ cd /\n$ mkdir ../a\n").is_err());
+        assert!(process_buf("$ mkdir foo\n$ rm -rf foo\n").is_ok());
+        assert!(process_buf("$ mkdir foo\n$ rm -x foo\n").is_err());
+        assert!(process_buf("$ mkdir y\n$ mv -- -x y\n").is_err());
+        assert!(process_buf("$ ls -l\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query() -> Result<(), Error> {
+        let node_buffer = process_buf(TEST_BUF)?;
+        let variables = default_variables(node_buffer.total_size(0));
+        assert_eq!(variables["free_needed"], 8381165);
+
+        let result = node_buffer.query("dirs where size <= 100000 | sum", &variables)?;
+        assert_eq!(result, QueryResult::Number(95437));
+
+        let result = node_buffer.query("dirs where size >= free_needed | min size", &variables)?;
+        assert_eq!(result.to_string(), "24933642 /d\n");
+
+        let result = node_buffer.query("files where name ~ \"*.*\" | top 2 by size", &variables)?;
+        assert_eq!(result.to_string(), "14848514 /b.txt\n8504156 /c.dat\n");
+
+        let result = node_buffer.query(
+            "files where path ~ \"/a/*\" and size > 10000 | sort by name",
+            &variables,
+        )?;
+        assert_eq!(result.to_string(), "29116 /a/f\n62596 /a/h.lst\n");
+
+        let result = node_buffer.query("dirs | count", &variables)?;
+        assert_eq!(result, QueryResult::Number(4));
+
+        assert!(node_buffer
+            .query("dirs where size ~ 10", &variables)
+            .is_err());
+        assert!(node_buffer
+            .query("dirs where size < unknown", &variables)
+            .is_err());
+        assert!(node_buffer.query("dirs | sum | count", &variables).is_err());
+        assert!(node_buffer.query("links", &variables).is_err());
+
+        assert!(glob_match("*.log", "x.log"));
+        assert!(glob_match("a?c*", "abc"));
+        assert!(!glob_match("*.log", "x.logs"));
+        Ok(())
+    }
+
+    fn recomputed_size(node_buffer: &NodeBuffer, index: usize) -> usize {
+        match &node_buffer.buffer[index] {
+            Inode::Directory {
+                child_directories,
+                child_files,
+                ..
+            } => child_directories
+                .values()
+                .chain(child_files.values())
+                .map(|i| recomputed_size(node_buffer, *i))
+                .sum(),
+            Inode::File { size, .. } => *size,
+        }
+    }
+
+    #[test]
+    fn test_cached_sizes() -> Result<(), Error> {
+        let mut node_buffer = process_buf(TEST_BUF)?;
+        node_buffer.move_node("/a/e", "/d").unwrap();
+        node_buffer.move_node("/b.txt", "/d/k").unwrap();
+        node_buffer.change_current_directory("/a").unwrap();
+        node_buffer.insert_file("f", 1).unwrap();
+        node_buffer.insert_file("new", 10).unwrap();
+        node_buffer.remove("/d/d.log").unwrap();
+
+        let reachable = node_buffer.query_rows(0, "/".into(), QuerySource::Dirs);
+        assert_eq!(reachable.len(), 4);
+        for (index, node) in node_buffer.buffer.iter().enumerate() {
+            if matches!(node, Inode::Directory { .. }) {
+                assert_eq!(node.size(), recomputed_size(&node_buffer, index));
+            }
+        }
+        assert_eq!(
+            node_buffer.total_size(0),
+            48381165 - 29116 + 1 + 10 - 8033020 - 7214296
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot() -> Result<(), Error> {
+        let node_buffer = process_buf(TEST_BUF)?;
+        let json = serde_json::to_string(&node_buffer.snapshot(0))?;
+        let snapshot: Snapshot = serde_json::from_str(&json)?;
+        let reloaded = NodeBuffer::from_snapshot(&snapshot)?;
+        assert_eq!(reloaded.debug_output(0, 0), TEST_OUTPUT);
+        assert_eq!(reloaded.total_size(0), 48381165);
+
+        let buf = format!(
+            "{TEST_BUF}$ cd /\n$ rm a/e\n$ ls\n100 b.txt\n$ mkdir x\n$ cd x\n$ ls\n5 new\n"
+        );
+        let changed = process_buf(&buf)?;
+        let diff = snapshot.diff(&changed.snapshot(0));
+        assert_eq!(
+            diff.to_string(),
+            "- /a/e/i (584)\n~ /b.txt (14848514 -> 100)\n+ /x/new (5)\n\
+             dir / -14848993\ndir /a -584\ndir /a/e -584\ndir /x +5\n"
+        );
+        assert_eq!(snapshot.diff(&snapshot), SnapshotDiff::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_exporters() -> Result<(), Error> {
+        let node_buffer = process_buf(TEST_BUF)?;
+
+        let tree = node_buffer.json_tree(0);
+        assert_eq!(tree["size"], 48381165);
+        assert_eq!(tree["children"][0]["name"], "a");
+        assert_eq!(
+            tree["children"][0]["children"][0]["children"][0]["size"],
+            584
+        );
+        assert_eq!(tree["children"][2]["type"], "file");
+
+        let ncdu = node_buffer.ncdu_export(1670000000);
+        assert_eq!(ncdu[0], 1);
+        assert_eq!(ncdu[2]["timestamp"], 1670000000);
+        assert_eq!(ncdu[3][0]["name"], "/");
+        assert_eq!(ncdu[3][1][0]["name"], "a");
+        assert_eq!(
+            ncdu[3][3],
+            json!({"name": "b.txt", "asize": 14848514, "dsize": 14848514})
+        );
+
+        let view = node_buffer.tree_view(0, 0);
+        let lines: Vec<&str> = view.lines().collect();
+        assert_eq!(lines.len(), 14);
+        assert_eq!(lines[0], "  46.1 MiB        /");
+        assert_eq!(lines[1], "  23.8 MiB  51.5%   d/");
+        assert_eq!(lines[6], "  14.2 MiB  30.7%   b.txt");
+        assert_eq!(lines[13], "     584 B 100.0%       i");
+
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        Ok(())
+    }
 }
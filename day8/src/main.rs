@@ -1,5 +1,6 @@
-use anyhow::Error;
+use anyhow::{format_err, Error};
 use clap::Parser;
+use std::cell::OnceCell;
 use std::fs;
 use std::path::PathBuf;
 
@@ -12,7 +13,7 @@ fn main() -> Result<(), Error> {
     let opts = Input::parse();
 
     let buf = fs::read_to_string(&opts.input)?;
-    let grid = TreeGrid::from_str(&buf);
+    let grid = TreeGrid::from_str(&buf)?;
     let n_visible = grid.number_visible();
     println!("n_visible {n_visible}");
     assert_eq!(n_visible, 1820);
@@ -23,140 +24,117 @@ fn main() -> Result<(), Error> {
 }
 
 #[derive(Debug, Default)]
-struct TreeGrid(Vec<Vec<u8>>);
+struct TreeGrid(Vec<Vec<u8>>, OnceCell<TreeMatrices>);
 
 impl TreeGrid {
-    fn from_str(buf: &str) -> Self {
+    fn from_str(buf: &str) -> Result<Self, Error> {
         let mut grid = Self::default();
-        for line in buf.split('\n') {
+        for (i, line) in buf.split('\n').enumerate() {
+            let line_number = i + 1;
             let mut row = Vec::new();
-            for c in line.chars() {
-                assert!(('0'..='9').contains(&c));
-                let height: u8 = c as u8 - b'0';
-                row.push(height);
+            for c in line.trim_end().chars() {
+                let height = c
+                    .to_digit(10)
+                    .ok_or_else(|| format_err!("Invalid height {c:?} on line {line_number}"))?;
+                row.push(height as u8);
             }
-            if !row.is_empty() {
-                grid.0.push(row);
+            if row.is_empty() {
+                continue;
             }
+            if let Some(first) = grid.0.first() {
+                if row.len() != first.len() {
+                    return Err(format_err!(
+                        "Line {line_number} has {} trees, expected {}",
+                        row.len(),
+                        first.len()
+                    ));
+                }
+            }
+            grid.0.push(row);
         }
-        let grid_size = grid.0.len();
-        for row in &grid.0 {
-            assert_eq!(row.len(), grid_size);
-        }
-        grid
+        Ok(grid)
     }
 
-    fn number_visible(&self) -> usize {
-        let grid_size = self.0.len();
-        let mut n_visible = 4 * grid_size - 4;
-        for idx in 1..grid_size - 1 {
-            for jdx in 1..grid_size - 1 {
-                let height = self.0[idx][jdx];
-                let mut is_visible = true;
-                for x in (0..idx).rev() {
-                    if self.0[x][jdx] >= height {
-                        is_visible = false;
-                        break;
-                    }
-                }
-                if is_visible {
-                    n_visible += 1;
-                    continue;
-                }
-                let mut is_visible = true;
-                for x in idx + 1..grid_size {
-                    if self.0[x][jdx] >= height {
-                        is_visible = false;
-                        break;
-                    }
-                }
-                if is_visible {
-                    n_visible += 1;
-                    continue;
-                }
+    fn rows(&self) -> usize {
+        self.0.len()
+    }
 
-                let mut is_visible = true;
-                for y in (0..jdx).rev() {
-                    if self.0[idx][y] >= height {
-                        is_visible = false;
-                        break;
-                    }
-                }
-                if is_visible {
-                    n_visible += 1;
-                    continue;
-                }
-                let mut is_visible = true;
-                for y in jdx + 1..grid_size {
-                    if self.0[idx][y] >= height {
-                        is_visible = false;
-                        break;
-                    }
-                }
-                if is_visible {
-                    n_visible += 1;
-                    continue;
-                }
-            }
-        }
-        n_visible
+    fn columns(&self) -> usize {
+        self.0.first().map_or(0, Vec::len)
     }
 
-    fn visibility_score(&self, x: usize, y: usize) -> usize {
-        let grid_size = self.0.len();
-        let mut visibility_score = 1;
-        let height = self.0[x][y];
-        let mut n_trees = 0;
-        for idx in (0..x).rev() {
-            n_trees += 1;
-            if self.0[idx][y] >= height {
-                break;
-            }
-        }
-        visibility_score *= n_trees;
+    fn matrices(&self) -> &TreeMatrices {
+        self.1.get_or_init(|| self.sweep_matrices())
+    }
 
-        let mut n_trees = 0;
-        for idx in x + 1..grid_size {
-            n_trees += 1;
-            if self.0[idx][y] >= height {
-                break;
-            }
+    fn sweep_matrices(&self) -> TreeMatrices {
+        let (rows, columns) = (self.rows(), self.columns());
+        let mut matrices = TreeMatrices {
+            visible: vec![vec![false; columns]; rows],
+            scores: vec![vec![1; columns]; rows],
+        };
+        let mut stack = Vec::with_capacity(rows.max(columns));
+        for x in 0..rows {
+            self.sweep(columns, |i| (x, i), &mut matrices, &mut stack);
+            self.sweep(columns, |i| (x, columns - 1 - i), &mut matrices, &mut stack);
         }
-        visibility_score *= n_trees;
-
-        let mut n_trees = 0;
-        for idy in (0..y).rev() {
-            n_trees += 1;
-            if self.0[x][idy] >= height {
-                break;
-            }
+        for y in 0..columns {
+            self.sweep(rows, |i| (i, y), &mut matrices, &mut stack);
+            self.sweep(rows, |i| (rows - 1 - i, y), &mut matrices, &mut stack);
         }
-        visibility_score *= n_trees;
+        matrices
+    }
+
+    fn number_visible(&self) -> usize {
+        self.matrices().number_visible()
+    }
 
-        let mut n_trees = 0;
-        for idy in y + 1..grid_size {
-            n_trees += 1;
-            if self.0[x][idy] >= height {
-                break;
+    fn maximum_visibility_score(&self) -> usize {
+        self.matrices().maximum_visibility_score()
+    }
+
+    fn sweep(
+        &self,
+        length: usize,
+        cell: impl Fn(usize) -> (usize, usize),
+        matrices: &mut TreeMatrices,
+        stack: &mut Vec<usize>,
+    ) {
+        let height_at = |i: usize| {
+            let (x, y) = cell(i);
+            self.0[x][y]
+        };
+        stack.clear();
+        let mut tallest = None;
+        for i in 0..length {
+            let (x, y) = cell(i);
+            let height = self.0[x][y];
+            if tallest.is_none_or(|t| height > t) {
+                matrices.visible[x][y] = true;
+                tallest = Some(height);
+            }
+            while stack.last().is_some_and(|j| height_at(*j) < height) {
+                stack.pop();
             }
+            matrices.scores[x][y] *= stack.last().map_or(i, |j| i - j);
+            stack.push(i);
         }
-        visibility_score *= n_trees;
+    }
+}
+
+#[derive(Debug)]
+struct TreeMatrices {
+    visible: Vec<Vec<bool>>,
+    scores: Vec<Vec<usize>>,
+}
 
-        visibility_score
+impl TreeMatrices {
+    fn number_visible(&self) -> usize {
+        self.visible.iter().flatten().filter(|v| **v).count()
     }
 
     fn maximum_visibility_score(&self) -> usize {
-        let mut max_score = 0;
-        let grid_size = self.0.len();
-        for idx in 0..grid_size {
-            for jdx in 0..grid_size {
-                let score = self.visibility_score(idx, jdx);
-                if score > max_score {
-                    max_score = score;
-                }
-            }
-        }
-        max_score
+        self.scores.iter().flatten().copied().max().unwrap_or(0)
     }
 }
 
@@ -172,9 +150,54 @@ pub static TEST_GRID: &str = "\
 mod tests {
     use super::*;
 
+    impl TreeGrid {
+        fn visibility_score(&self, x: usize, y: usize) -> usize {
+            let (rows, columns) = (self.rows(), self.columns());
+            let mut visibility_score = 1;
+            let height = self.0[x][y];
+            let mut n_trees = 0;
+            for idx in (0..x).rev() {
+                n_trees += 1;
+                if self.0[idx][y] >= height {
+                    break;
+                }
+            }
+            visibility_score *= n_trees;
+
+            let mut n_trees = 0;
+            for idx in x + 1..rows {
+                n_trees += 1;
+                if self.0[idx][y] >= height {
+                    break;
+                }
+            }
+            visibility_score *= n_trees;
+
+            let mut n_trees = 0;
+            for idy in (0..y).rev() {
+                n_trees += 1;
+                if self.0[x][idy] >= height {
+                    break;
+                }
+            }
+            visibility_score *= n_trees;
+
+            let mut n_trees = 0;
+            for idy in y + 1..columns {
+                n_trees += 1;
+                if self.0[x][idy] >= height {
+                    break;
+                }
+            }
+            visibility_score *= n_trees;
+
+            visibility_score
+        }
+    }
+
     #[test]
     fn test_grid_from_str() -> Result<(), Error> {
-        let grid = TreeGrid::from_str(TEST_GRID);
+        let grid = TreeGrid::from_str(TEST_GRID)?;
         println!("{grid:?}");
 
         assert_eq!(grid.number_visible(), 21);
@@ -183,10 +206,64 @@ mod tests {
 
     #[test]
     fn test_maximum_visibility_score() -> Result<(), Error> {
-        let grid = TreeGrid::from_str(TEST_GRID);
+        let grid = TreeGrid::from_str(TEST_GRID)?;
         assert_eq!(grid.visibility_score(1, 2), 4);
         assert_eq!(grid.visibility_score(3, 2), 8);
         assert_eq!(grid.maximum_visibility_score(), 8);
         Ok(())
     }
+
+    #[test]
+    fn test_rectangular_grid() -> Result<(), Error> {
+        let wide = TreeGrid::from_str("30373\n25512\n65332\n")?;
+        assert_eq!((wide.rows(), wide.columns()), (3, 5));
+        assert_eq!(wide.number_visible(), 14);
+        assert_eq!(wide.visibility_score(1, 2), 2);
+        assert_eq!(wide.maximum_visibility_score(), 2);
+
+        let tall = TreeGrid::from_str("326\n055\n353\n713\n322\n")?;
+        assert_eq!((tall.rows(), tall.columns()), (5, 3));
+        assert_eq!(tall.number_visible(), 14);
+        assert_eq!(tall.visibility_score(2, 1), 2);
+        assert_eq!(tall.maximum_visibility_score(), 2);
+
+        assert_eq!(TreeGrid::from_str("12\n")?.number_visible(), 2);
+        assert_eq!(TreeGrid::from_str("")?.number_visible(), 0);
+        let err = TreeGrid::from_str("123\n12\n").unwrap_err();
+        assert_eq!(err.to_string(), "Line 2 has 2 trees, expected 3");
+        let err = TreeGrid::from_str("12a\n123\n").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid height 'a' on line 1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrices() -> Result<(), Error> {
+        let grid = TreeGrid::from_str(TEST_GRID)?;
+        let TreeMatrices { visible, scores } = grid.matrices();
+        assert_eq!(visible[1], vec![true, true, true, false, true]);
+        assert_eq!(visible[2], vec![true, true, false, true, true]);
+        assert_eq!(scores[3], vec![0, 1, 8, 3, 0]);
+
+        let mut seed = 12345u64;
+        let buf: String = (0..17)
+            .map(|_| {
+                let mut line: String = (0..41)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        char::from(b'0' + (seed >> 33) as u8 % 10)
+                    })
+                    .collect();
+                line.push('\n');
+                line
+            })
+            .collect();
+        let grid = TreeGrid::from_str(&buf)?;
+        let scores = &grid.matrices().scores;
+        for (x, row) in scores.iter().enumerate() {
+            for (y, score) in row.iter().enumerate() {
+                assert_eq!(*score, grid.visibility_score(x, y));
+            }
+        }
+        Ok(())
+    }
 }